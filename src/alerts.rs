@@ -0,0 +1,111 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::history::{History, Record, Run};
+use crate::http;
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn find<'a>(run: &'a Run, record: &Record) -> Option<&'a Record> {
+    run.records
+        .iter()
+        .find(|previous| previous.attr == record.attr && previous.output == record.output)
+}
+
+/// Start of the run a failure has been ongoing since, going back through consecutive failing runs
+fn failing_since(runs: &[Run], record: &Record) -> u64 {
+    let mut since = runs.last().map(|run| run.started).unwrap_or_default();
+
+    for run in runs.iter().rev().skip(1) {
+        match find(run, record) {
+            Some(previous) if previous.status.is_failure() => since = run.started,
+            _ => break,
+        }
+    }
+
+    since
+}
+
+fn alert(record: &Record, kind: &str, previous: Option<&Record>, since: Option<u64>) -> Value {
+    json!({
+        "kind": kind,
+        "attr": record.attr,
+        "drv": record.drv,
+        "output": record.output,
+        "status": record.status.name(),
+        "previous_status": previous.map(|previous| previous.status.name()),
+        "failing_since": since,
+    })
+}
+
+/// Changes in the latest run worth telling someone about: FODs that broke, were fixed or are new,
+/// and failures that have lasted another `remind_after` days
+pub fn transitions(history: &History, remind_after: Option<u64>) -> Vec<Value> {
+    let Some((latest, earlier)) = history.runs.split_last() else {
+        return vec![];
+    };
+    let Some(previous_run) = earlier.last() else {
+        // Everything is new on the first run, so only failures are worth hearing about
+        return latest
+            .records
+            .iter()
+            .filter(|record| record.status.is_failure())
+            .map(|record| alert(record, "broken", None, Some(latest.started)))
+            .collect();
+    };
+
+    let mut alerts = Vec::new();
+    for record in &latest.records {
+        let previous = find(previous_run, record);
+
+        match previous {
+            None => alerts.push(alert(record, "new", None, None)),
+            Some(previous) if previous.status.is_failure() != record.status.is_failure() => {
+                let kind = if record.status.is_failure() {
+                    "broken"
+                } else {
+                    "fixed"
+                };
+                alerts.push(alert(record, kind, Some(previous), None));
+            }
+            Some(previous) if record.status.is_failure() => {
+                let Some(days) = remind_after.filter(|days| *days > 0) else {
+                    continue;
+                };
+
+                // Reminders are due every `days` since the failure started, so a reminder is only
+                // sent by the first run past each of those points
+                let since = failing_since(&history.runs, record);
+                let period = days * DAY;
+                let due = |time: u64| time.saturating_sub(since) / period;
+                if due(latest.started) > due(previous_run.started) {
+                    alerts.push(alert(record, "reminder", Some(previous), Some(since)));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    alerts
+}
+
+/// Posts the latest run's transitions to a webhook, sending nothing when there are none
+pub fn notify(url: &str, history: &History, remind_after: Option<u64>) -> Result<usize> {
+    let alerts = transitions(history, remind_after);
+    if alerts.is_empty() {
+        return Ok(0);
+    }
+
+    let latest = history.latest().expect("Latest run");
+    http::post(
+        url,
+        &[],
+        Some(&json!({
+            "started": latest.started,
+            "revision": latest.revision,
+            "alerts": alerts,
+        })),
+    )?;
+
+    Ok(alerts.len())
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use serde_json::{json, Value};
+
+use crate::history::{History, Run};
+use crate::report::Status;
+use crate::server::{Request, Response};
+use crate::timeseries;
+
+/// Records in a page of `/records` unless the query asks for fewer, and the most it can ask for
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+fn summary(run: &Run) -> Value {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for record in &run.records {
+        *counts.entry(record.status.name()).or_default() += 1;
+    }
+
+    json!({
+        "started": run.started,
+        "finished": run.finished,
+        "revision": run.revision,
+        "counts": counts,
+    })
+}
+
+fn failures(history: &History) -> Value {
+    let Some(run) = history.latest() else {
+        return json!([]);
+    };
+
+    run.records
+        .iter()
+        .filter(|record| record.status.is_failure())
+        .map(|record| record.to_json())
+        .collect()
+}
+
+/// Every recorded result for an attr, oldest run first
+fn attr(history: &History, name: &str) -> Option<Value> {
+    let records = history
+        .runs
+        .iter()
+        .flat_map(|run| {
+            run.records
+                .iter()
+                .filter(|record| record.attr == name)
+                .map(|record| {
+                    let mut value = record.to_json();
+                    value["run"] = json!(run.started);
+                    value["revision"] = json!(run.revision);
+                    value
+                })
+        })
+        .collect::<Vec<Value>>();
+
+    (!records.is_empty()).then(|| json!(records))
+}
+
+/// Records matching a `/records` query, for consumers asking targeted questions of a large
+/// history instead of fetching whole runs
+///
+/// Records come from the latest run unless `since` or `until` give a range of run start times,
+/// and can be filtered by `attr_prefix`, `fetcher` and a comma-separated list of `status` names,
+/// with `failing` standing for every failure. Results are paged with `offset` and `limit`.
+fn records(history: &History, query: &BTreeMap<String, String>) -> Result<Value, String> {
+    let number = |name: &str, default: u64| match query.get(name) {
+        Some(value) => value
+            .parse::<u64>()
+            .map_err(|_| format!("invalid value {} for {}", value, name)),
+        None => Ok(default),
+    };
+
+    let since = number("since", 0)?;
+    let until = number("until", u64::MAX)?;
+    let offset = number("offset", 0)? as usize;
+    let limit = number("limit", DEFAULT_LIMIT)?.min(MAX_LIMIT) as usize;
+
+    let statuses = match query.get("status") {
+        Some(names) => names
+            .split(',')
+            .map(|name| {
+                if name == "failing" {
+                    return Ok(None);
+                }
+                Status::from_name(name)
+                    .map(Some)
+                    .ok_or(format!("unknown status {}", name))
+            })
+            .collect::<Result<Vec<Option<Status>>, String>>()?,
+        None => vec![],
+    };
+    let attr_prefix = query.get("attr_prefix").map(String::as_str);
+    let fetcher = query.get("fetcher").map(String::as_str);
+
+    let runs: Vec<&Run> = if query.contains_key("since") || query.contains_key("until") {
+        history
+            .runs
+            .iter()
+            .filter(|run| (since..=until).contains(&run.started))
+            .collect()
+    } else {
+        history.latest().into_iter().collect()
+    };
+
+    let matching = runs
+        .iter()
+        .flat_map(|run| run.records.iter().map(move |record| (*run, record)))
+        .filter(|(_, record)| {
+            attr_prefix.is_none_or(|prefix| record.attr.starts_with(prefix))
+                && fetcher.is_none_or(|fetcher| record.fetcher.as_deref() == Some(fetcher))
+                && (statuses.is_empty()
+                    || statuses.iter().any(|status| match status {
+                        Some(status) => record.status == *status,
+                        None => record.status.is_failure(),
+                    }))
+        })
+        .collect::<Vec<_>>();
+
+    let page = matching
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(run, record)| {
+            let mut value = record.to_json();
+            value["run"] = json!(run.started);
+            value["revision"] = json!(run.revision);
+            value
+        })
+        .collect::<Vec<Value>>();
+
+    Ok(json!({
+        "total": matching.len(),
+        "offset": offset,
+        "limit": limit,
+        "records": page,
+    }))
+}
+
+/// Answers REST requests about the runs in the history
+pub fn handle(history: &RwLock<History>, request: &Request) -> Response {
+    let history = history.read().expect("Acquiring history lock");
+    let path = request.path.trim_end_matches('/');
+
+    match path {
+        "/health" => Response::json(
+            200,
+            &json!({
+                "status": "ok",
+                "runs": history.runs.len(),
+                "latest": history.latest().map(|run| run.started),
+            }),
+        ),
+        "/runs" => Response::json(200, &history.runs.iter().map(summary).collect::<Value>()),
+        "/failures" => Response::json(200, &failures(&history)),
+        "/records" => match records(&history, &request.query) {
+            Ok(records) => Response::json(200, &records),
+            Err(err) => Response::json(400, &json!({ "error": err })),
+        },
+        "/timeseries" => Response::json(200, &timeseries::points(&history)),
+        _ => match path
+            .strip_prefix("/attr/")
+            .and_then(|name| attr(&history, name))
+        {
+            Some(records) => Response::json(200, &records),
+            None => Response::not_found(),
+        },
+    }
+}
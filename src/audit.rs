@@ -0,0 +1,149 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::history::now;
+use crate::plan::quote;
+
+/// Nix options whose values are credentials, kept out of the audit log
+const SECRET_OPTIONS: &[&str] = &["access-tokens"];
+
+static AUDIT_LOG: Mutex<Option<File>> = Mutex::new(None);
+
+/// Starts appending every Nix command run to the audit log at `path`
+pub fn open(path: &Path) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Opening audit log {}", path.display()))?;
+
+    *AUDIT_LOG.lock().expect("Acquiring audit log lock") = Some(file);
+    Ok(())
+}
+
+fn lossy(value: &OsStr) -> String {
+    value.to_string_lossy().into_owned()
+}
+
+/// Arguments with the values of secret options replaced
+fn redacted_args(command: &Command) -> Vec<String> {
+    let mut args = command.get_args().map(lossy).collect::<Vec<_>>();
+
+    for i in 2..args.len() {
+        if args[i - 2] == "--option" && SECRET_OPTIONS.contains(&args[i - 1].as_str()) {
+            args[i] = "<redacted>".to_string();
+        }
+    }
+
+    args
+}
+
+/// Configuration with the lines setting secret options replaced
+fn redacted_config(config: &str) -> String {
+    config
+        .lines()
+        .map(|line| match line.split_once('=') {
+            Some((name, _)) if SECRET_OPTIONS.contains(&name.trim()) => {
+                format!("{}= <redacted>", name)
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Command line reproducing the command in a shell, starting from an empty environment as it does,
+/// with the same secrets redacted as in the audit log
+pub fn shell(command: &Command) -> String {
+    let mut words = Vec::new();
+
+    if let Some(dir) = command.get_current_dir() {
+        words.extend([
+            "cd".to_string(),
+            quote(&dir.display().to_string()),
+            "&&".to_string(),
+        ]);
+    }
+    words.extend(["env".to_string(), "-i".to_string()]);
+    for (name, value) in command.get_envs() {
+        let Some(value) = value else {
+            continue;
+        };
+        let name = lossy(name);
+        let value = if name == "NIX_CONFIG" {
+            redacted_config(&lossy(value))
+        } else {
+            lossy(value)
+        };
+        words.push(quote(&format!("{}={}", name, value)));
+    }
+    words.push(quote(&lossy(command.get_program())));
+    words.extend(redacted_args(command).iter().map(|arg| quote(arg)));
+
+    words.join(" ")
+}
+
+/// Variables the command sets differently from our own environment, as the rest of its
+/// environment is ours passed through or cleared
+fn env_delta(command: &Command) -> Value {
+    command
+        .get_envs()
+        .filter_map(|(name, value)| {
+            let value = value?;
+            if env::var_os(name).as_deref() == Some(value) {
+                return None;
+            }
+
+            let name = lossy(name);
+            let value = if name == "NIX_CONFIG" {
+                redacted_config(&lossy(value))
+            } else {
+                lossy(value)
+            };
+            Some((name, Value::String(value)))
+        })
+        .collect::<serde_json::Map<String, Value>>()
+        .into()
+}
+
+/// Appends a command that ran, or failed to start without a status, to the audit log if one is
+/// open
+pub fn record(command: &Command, status: Option<ExitStatus>, duration: Duration) {
+    let mut audit_log = AUDIT_LOG.lock().expect("Acquiring audit log lock");
+    let Some(file) = audit_log.as_mut() else {
+        return;
+    };
+
+    let passed = command
+        .get_envs()
+        .filter(|(name, value)| value.is_some() && env::var_os(name).as_deref() == *value)
+        .map(|(name, _)| lossy(name))
+        .collect::<Vec<_>>();
+    let entry = json!({
+        "time": now(),
+        "program": lossy(command.get_program()),
+        "args": redacted_args(command),
+        "env": env_delta(command),
+        "passed_env": passed,
+        "cwd": command
+            .get_current_dir()
+            .map(|dir| dir.display().to_string())
+            .or_else(|| env::current_dir().ok().map(|dir| dir.display().to_string())),
+        "exit_code": status.and_then(|status| status.code()),
+        "started": status.is_some(),
+        "duration": duration.as_secs_f64(),
+    });
+
+    if let Err(err) = writeln!(file, "{}", entry) {
+        warn!("Error writing audit log, ignoring: {}", err);
+    }
+}
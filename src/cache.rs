@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::http;
+
+pub const DEFAULT_BINARY_CACHE: &str = "https://cache.nixos.org";
+
+#[derive(Debug, Clone)]
+pub struct NarInfo {
+    pub nar_hash: String,
+    pub nar_size: Option<u64>,
+}
+
+pub fn narinfo(cache_url: &str, out_path: &Path) -> Result<Option<NarInfo>> {
+    let hash_part = out_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('-').next())
+        .ok_or(anyhow!("Invalid store path {}", out_path.display()))?;
+
+    let Some(body) = http::get(&format!(
+        "{}/{}.narinfo",
+        cache_url.trim_end_matches('/'),
+        hash_part
+    ))?
+    else {
+        return Ok(None);
+    };
+
+    let body = String::from_utf8(body).context("Decoding narinfo")?;
+
+    let field = |name: &str| {
+        body.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+            .map(str::trim)
+    };
+
+    Ok(Some(NarInfo {
+        nar_hash: field("NarHash")
+            .ok_or(anyhow!("No NarHash in narinfo for {}", out_path.display()))?
+            .to_string(),
+        nar_size: field("NarSize").and_then(|size| size.parse().ok()),
+    }))
+}
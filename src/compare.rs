@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::drv::Derivation;
+
+/// FOD outputs of a revision by derivation name and output, then by hash with the attr using them
+///
+/// Several FODs can share a name, e.g. `source`, so each name can have more than one hash.
+pub type Surface = BTreeMap<(String, String), BTreeMap<String, String>>;
+
+/// An added, removed or hash-changed FOD output between two revisions
+pub struct Change<'a> {
+    pub kind: &'static str,
+    pub name: &'a str,
+    pub output: &'a str,
+    pub attr: &'a str,
+    pub old: Vec<&'a str>,
+    pub new: Vec<&'a str>,
+}
+
+/// Derivation name without the store path hash, so it stays put across revisions
+pub fn name(drv: &Path) -> String {
+    let name = drv
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = name.strip_suffix(".drv").unwrap_or(&name);
+
+    name.split_once('-')
+        .map_or(name, |(_, name)| name)
+        .to_string()
+}
+
+pub fn add(surface: &mut Surface, drv: &Path, derivation: &Derivation, attr: &str) {
+    for output in &derivation.outputs {
+        surface
+            .entry((name(drv), output.name.clone()))
+            .or_default()
+            .entry(output.sri_hash())
+            .or_insert(attr.to_string());
+    }
+}
+
+/// Changes from `old` to `new`, where a name keeping some of its hashes and gaining others only
+/// counts as added, and one losing hashes without gaining any as removed
+pub fn diff<'a>(old: &'a Surface, new: &'a Surface) -> Vec<Change<'a>> {
+    let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    const EMPTY: &BTreeMap<String, String> = &BTreeMap::new();
+    let mut changes = Vec::new();
+    for key in keys {
+        let before = old.get(key).unwrap_or(EMPTY);
+        let after = new.get(key).unwrap_or(EMPTY);
+
+        let gone = before
+            .iter()
+            .filter(|(hash, _)| !after.contains_key(*hash))
+            .collect::<Vec<_>>();
+        let came = after
+            .iter()
+            .filter(|(hash, _)| !before.contains_key(*hash))
+            .collect::<Vec<_>>();
+
+        let kind = match (gone.is_empty(), came.is_empty()) {
+            (true, true) => continue,
+            (true, false) => "added",
+            (false, true) => "removed",
+            (false, false) => "hash-changed",
+        };
+        let (_, attr) = came.first().or(gone.first()).expect("Changed hash");
+
+        changes.push(Change {
+            kind,
+            name: &key.0,
+            output: &key.1,
+            attr,
+            old: gone.iter().map(|(hash, _)| hash.as_str()).collect(),
+            new: came.iter().map(|(hash, _)| hash.as_str()).collect(),
+        });
+    }
+
+    changes
+}
+
+/// Renders the changes as text grouped by kind, or as JSON
+pub fn render(changes: &[Change], format: &str) -> String {
+    if format == "json" {
+        return format!(
+            "{}\n",
+            changes
+                .iter()
+                .map(|change| json!({
+                    "kind": change.kind,
+                    "name": change.name,
+                    "output": change.output,
+                    "attr": change.attr,
+                    "old": change.old,
+                    "new": change.new,
+                }))
+                .collect::<serde_json::Value>()
+        );
+    }
+
+    let mut text = String::new();
+    for (kind, title) in [
+        ("added", "Added"),
+        ("removed", "Removed"),
+        ("hash-changed", "Hash changed"),
+    ] {
+        let of_kind = changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .collect::<Vec<_>>();
+        text.push_str(&format!("{} ({}):\n", title, of_kind.len()));
+
+        for change in of_kind {
+            let output = if change.output == "out" {
+                String::new()
+            } else {
+                format!("^{}", change.output)
+            };
+            let hashes = match kind {
+                "added" => change.new.join(", "),
+                "removed" => change.old.join(", "),
+                _ => format!("{} -> {}", change.old.join(", "), change.new.join(", ")),
+            };
+
+            text.push_str(&format!(
+                "  {}{} from {}: {}\n",
+                change.name, output, change.attr, hashes
+            ));
+        }
+    }
+
+    text
+}
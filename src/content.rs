@@ -0,0 +1,162 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::drv::Output;
+use crate::reclone::GITHUB_ARCHIVE;
+
+/// How the content inside a flat-hashed download with a hash mismatch compares between the file
+/// the hash was taken of and the one upstream serves now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Content {
+    /// Same files in an archive GitHub generates on request, which changes whenever GitHub changes
+    /// how it compresses
+    Regenerated,
+    /// Same content compressed or archived differently
+    Recompressed,
+    Changed,
+}
+
+impl Content {
+    /// Stable name for the comparison in machine-readable output
+    pub fn name(self) -> &'static str {
+        match self {
+            Content::Regenerated => "github-tarball-unstable",
+            Content::Recompressed => "recompressed",
+            Content::Changed => "content-changed",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Content::Regenerated => "same content, GitHub regenerated the archive differently",
+            Content::Recompressed => "same content, different compression",
+            Content::Changed => "different content entirely",
+        }
+    }
+}
+
+/// Compressed and archive formats told apart by their first bytes
+enum Format {
+    /// Decompressed by piping through the program's `-dc`
+    Compressed(&'static str),
+    Tar,
+    Zip,
+}
+
+fn detect(header: &[u8]) -> Option<Format> {
+    if header.starts_with(&[0x1f, 0x8b]) {
+        Some(Format::Compressed("gzip"))
+    } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(Format::Compressed("xz"))
+    } else if header.starts_with(b"BZh") {
+        Some(Format::Compressed("bzip2"))
+    } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some(Format::Compressed("zstd"))
+    } else if header.starts_with(b"PK\x03\x04") {
+        Some(Format::Zip)
+    } else if header.get(257..262) == Some(b"ustar") {
+        Some(Format::Tar)
+    } else {
+        None
+    }
+}
+
+/// Whether an output is hashed as the bytes of the file downloaded rather than as its unpacked
+/// content, so differently compressed content fails its hash
+pub fn comparable(output: &Output) -> bool {
+    !output.hash_algo.starts_with("r:")
+}
+
+/// Whether a download is an archive GitHub generates on request
+pub fn github_archive(urls: &[String]) -> bool {
+    urls.first().is_some_and(|url| GITHUB_ARCHIVE.is_match(url))
+}
+
+fn header(path: &Path) -> Result<Vec<u8>> {
+    let mut header = Vec::new();
+    File::open(path)
+        .context(format!("Opening {}", path.display()))?
+        .take(512)
+        .read_to_end(&mut header)
+        .context(format!("Reading {}", path.display()))?;
+
+    Ok(header)
+}
+
+fn tool(command: &mut Command, name: &str) -> Result<()> {
+    let output = command
+        .stdout(Stdio::null())
+        .output()
+        .context(format!("Running {}", name))?;
+
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Decompresses and extracts a download into `dir`, which is created, leaving a file that is
+/// compressed but not an archive decompressed as `content`, and returning false for a download
+/// that is neither
+pub fn unpack(file: &Path, dir: &Path) -> Result<bool> {
+    let Some(format) = detect(&header(file)?) else {
+        return Ok(false);
+    };
+    fs::create_dir_all(dir).context(format!("Creating directory {}", dir.display()))?;
+
+    match format {
+        Format::Compressed(program) => {
+            // Kept out of `dir` until it is known not to be a tarball, whose files could clash
+            let decompressed = dir.with_extension("decompressed");
+            let output = File::create(&decompressed)
+                .context(format!("Creating {}", decompressed.display()))?;
+            let status = Command::new(program)
+                .arg("-dc")
+                .arg(file)
+                .stdout(output)
+                .status()
+                .context(format!("Running {}", program))?;
+            if !status.success() {
+                bail!("{} failed for {}", program, file.display());
+            }
+
+            // Compressed tarballs are compared by the files in them
+            if matches!(detect(&header(&decompressed)?), Some(Format::Tar)) {
+                unpack(&decompressed, dir)?;
+                fs::remove_file(&decompressed)
+                    .context(format!("Removing {}", decompressed.display()))?;
+            } else {
+                fs::rename(&decompressed, dir.join("content"))
+                    .context(format!("Moving {}", decompressed.display()))?;
+            }
+        }
+        Format::Tar => tool(
+            Command::new("tar")
+                .arg("--extract")
+                .arg("--file")
+                .arg(file)
+                .arg("--directory")
+                .arg(dir),
+            "tar",
+        )?,
+        Format::Zip => tool(
+            Command::new("unzip")
+                .arg("-qq")
+                .arg(file)
+                .arg("-d")
+                .arg(dir),
+            "unzip",
+        )?,
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,115 @@
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::http;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How far a failed fetch's upstream could be reached, to tell a domain that is gone from a server
+/// that is up but no longer serves the content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reachability {
+    Unresolvable,
+    Unreachable,
+    Reachable,
+}
+
+impl Reachability {
+    /// Stable name for the diagnosis in machine-readable output
+    pub fn name(self) -> &'static str {
+        match self {
+            Reachability::Unresolvable => "unresolvable",
+            Reachability::Unreachable => "unreachable",
+            Reachability::Reachable => "reachable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Reachability> {
+        [
+            Reachability::Unresolvable,
+            Reachability::Unreachable,
+            Reachability::Reachable,
+        ]
+        .into_iter()
+        .find(|reachability| reachability.name() == name)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Reachability::Unresolvable => "upstream host does not resolve",
+            Reachability::Unreachable => "upstream host resolves but does not accept connections",
+            Reachability::Reachable => "upstream server is up",
+        }
+    }
+
+    /// Triage tag for the diagnosis
+    pub fn tag(self) -> &'static str {
+        match self {
+            Reachability::Unresolvable => "domain-gone",
+            Reachability::Unreachable => "host-down",
+            Reachability::Reachable => "content-missing",
+        }
+    }
+}
+
+/// Host and port a URL connects to, for the schemes with a well-known port
+fn address(url: &str) -> Option<(String, u16)> {
+    let port = match url.split_once("://")?.0 {
+        "http" => 80,
+        "https" => 443,
+        "ftp" => 21,
+        _ => return None,
+    };
+    let host = http::host(url)?;
+
+    // IPv6 addresses are bracketed, and may be followed by a port
+    if let Some(bracketed) = host.strip_prefix('[') {
+        let (ip, rest) = bracketed.split_once(']')?;
+        let port = rest
+            .strip_prefix(':')
+            .map_or(Some(port), |p| p.parse().ok())?;
+        return Some((ip.to_string(), port));
+    }
+
+    match host.split_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None => Some((host, port)),
+    }
+}
+
+fn diagnose((host, port): (String, u16)) -> Reachability {
+    let Ok(addrs) = (host.as_str(), port).to_socket_addrs() else {
+        return Reachability::Unresolvable;
+    };
+
+    if addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).is_ok())
+    {
+        Reachability::Reachable
+    } else {
+        Reachability::Unreachable
+    }
+}
+
+/// Furthest any of the URLs could be reached, by resolving their hosts and connecting to them, or
+/// `None` without any URLs to diagnose
+pub fn reachability(urls: &[String]) -> Option<Reachability> {
+    let mut addresses = urls
+        .iter()
+        .filter_map(|url| address(url))
+        .collect::<Vec<_>>();
+    addresses.sort();
+    addresses.dedup();
+
+    let mut furthest = None;
+    for address in addresses {
+        let reachability = diagnose(address);
+        if reachability == Reachability::Reachable {
+            return Some(reachability);
+        }
+        furthest = furthest.max(Some(reachability));
+    }
+
+    furthest
+}
@@ -0,0 +1,102 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::options::Options;
+use crate::report::format_size;
+use crate::store;
+
+pub fn free_space(path: &str) -> Result<u64> {
+    let output = Command::new("df")
+        .args(["--output=avail", "--block-size=1", path])
+        .output()
+        .context("Running df")?;
+
+    if !output.status.success() {
+        bail!("df failed for {}", path);
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .nth(1)
+        .ok_or(anyhow!("No free space in df output"))?
+        .trim()
+        .parse()
+        .context("Parsing free space")
+}
+
+/// Whether the store has less than `--min-free-space` free, so space should be freed early
+pub fn low_on_space(opts: &Options) -> bool {
+    opts.min_free_space
+        .is_some_and(|min_free| free_space(&store_dir()).is_ok_and(|free| free < min_free))
+}
+
+/// Store directory on disk, which is under the private store's root with `--private-store`
+pub fn store_dir() -> String {
+    let store_dir = env::var("NIX_STORE_DIR").unwrap_or("/nix/store".to_string());
+
+    store::physical(Path::new(&store_dir)).display().to_string()
+}
+
+/// Holds back new realisations while the store is low on space, collecting garbage to make room
+pub struct DiskMonitor {
+    lock: Mutex<()>,
+}
+
+impl DiskMonitor {
+    pub fn new() -> DiskMonitor {
+        DiskMonitor {
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn wait_for_space(&self, opts: &Options, gc: impl Fn(u64) -> Result<()>) {
+        let Some(min_free) = opts.min_free_space else {
+            return;
+        };
+
+        // Other workers block here until there is space again
+        let _guard = self.lock.lock().expect("Acquiring disk monitor mutex");
+
+        for attempt in 0.. {
+            let free = match free_space(&store_dir()) {
+                Ok(free) => free,
+                Err(err) => {
+                    warn!(
+                        "Error checking free space in the store, ignoring: {:#}",
+                        err
+                    );
+                    return;
+                }
+            };
+
+            if free >= min_free {
+                return;
+            }
+
+            if attempt == 0 {
+                info!(
+                    "Only {} free in the store, pausing realisations to collect garbage",
+                    format_size(free)
+                );
+
+                if let Err(err) = gc(opts.gc_max_freed.unwrap_or(min_free * 2)) {
+                    warn!("Error collecting garbage, ignoring: {:#}", err);
+                }
+            } else if attempt > 10 {
+                warn!(
+                    "Still only {} free in the store, resuming realisations anyway",
+                    format_size(free)
+                );
+                return;
+            } else {
+                thread::sleep(Duration::from_secs(30));
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::hash::{Encoding, Hash};
+use crate::store;
+
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub name: String,
+    pub path: String,
+    pub hash_algo: String,
+    pub hash: String,
+}
+
+impl Output {
+    /// Fixed hash in SRI form, as given when it cannot be parsed
+    pub fn sri_hash(&self) -> String {
+        Hash::parse(&self.hash, Some(&self.hash_algo))
+            .map(|hash| hash.encode(Encoding::Sri))
+            .unwrap_or(self.hash.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Derivation {
+    pub outputs: Vec<Output>,
+    pub env: HashMap<String, String>,
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn expect(&mut self, token: &[u8]) -> Result<()> {
+        if self.input[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Expected {:?} at offset {}",
+                String::from_utf8_lossy(token),
+                self.pos
+            ))
+        }
+    }
+
+    fn peek(&self, token: u8) -> bool {
+        self.input.get(self.pos) == Some(&token)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect(b"\"")?;
+
+        let mut value = Vec::new();
+        loop {
+            match self.input.get(self.pos) {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    value.push(match self.input.get(self.pos) {
+                        Some(b'n') => b'\n',
+                        Some(b'r') => b'\r',
+                        Some(b't') => b'\t',
+                        Some(c) => *c,
+                        None => bail!("Unterminated escape sequence"),
+                    });
+                }
+                Some(c) => value.push(*c),
+                None => bail!("Unterminated string"),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+
+        String::from_utf8(value).context("Decoding derivation string")
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        self.expect(b"[")?;
+
+        let mut items = Vec::new();
+        while !self.peek(b']') {
+            if !items.is_empty() {
+                self.expect(b",")?;
+            }
+            items.push(item(self)?);
+        }
+        self.pos += 1;
+
+        Ok(items)
+    }
+
+    /// Skips over any term, for fields whose shape depends on the derivation format version
+    fn skip(&mut self) -> Result<()> {
+        match self.input.get(self.pos) {
+            Some(b'"') => self.string().map(|_| ()),
+            Some(b'[') => self.list(Parser::skip).map(|_| ()),
+            Some(b'(') => {
+                self.pos += 1;
+                let mut first = true;
+                while !self.peek(b')') {
+                    if !first {
+                        self.expect(b",")?;
+                    }
+                    self.skip()?;
+                    first = false;
+                }
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(anyhow!("Unexpected term at offset {}", self.pos)),
+        }
+    }
+
+    fn derivation(&mut self) -> Result<Derivation> {
+        if self.input.starts_with(b"DrvWithVersion(") {
+            self.expect(b"DrvWithVersion(")?;
+            let _version = self.string()?;
+            self.expect(b",")?;
+        } else {
+            self.expect(b"Derive(")?;
+        }
+
+        let outputs = self.list(|p| {
+            p.expect(b"(")?;
+            let name = p.string()?;
+            p.expect(b",")?;
+            let path = p.string()?;
+            p.expect(b",")?;
+            let hash_algo = p.string()?;
+            p.expect(b",")?;
+            let hash = p.string()?;
+            p.expect(b")")?;
+
+            Ok(Output {
+                name,
+                path,
+                hash_algo,
+                hash,
+            })
+        })?;
+        self.expect(b",")?;
+
+        let _input_drvs = self.list(Parser::skip)?;
+        self.expect(b",")?;
+
+        let _input_srcs = self.list(Parser::string)?;
+        self.expect(b",")?;
+        let _platform = self.string()?;
+        self.expect(b",")?;
+        let _builder = self.string()?;
+        self.expect(b",")?;
+        let _args = self.list(Parser::string)?;
+        self.expect(b",")?;
+
+        let env = self
+            .list(|p| {
+                p.expect(b"(")?;
+                let name = p.string()?;
+                p.expect(b",")?;
+                let value = p.string()?;
+                p.expect(b")")?;
+
+                Ok((name, value))
+            })?
+            .into_iter()
+            .collect();
+        self.expect(b")")?;
+
+        Ok(Derivation { outputs, env })
+    }
+}
+
+impl Derivation {
+    pub fn parse(input: &[u8]) -> Result<Derivation> {
+        Parser { input, pos: 0 }.derivation()
+    }
+
+    /// Derivation from its entry in `nix derivation show` output, which newer Nix gives the
+    /// method of hashing apart from the algorithm
+    pub fn from_json(value: &serde_json::Value) -> Option<Derivation> {
+        let outputs = value["outputs"]
+            .as_object()?
+            .iter()
+            .map(|(name, output)| {
+                let algo = output["hashAlgo"].as_str().unwrap_or_default();
+                let hash_algo = match output["method"].as_str() {
+                    Some("nar") => format!("r:{}", algo),
+                    Some("text") => format!("text:{}", algo),
+                    _ => algo.to_string(),
+                };
+
+                Output {
+                    name: name.clone(),
+                    path: output["path"].as_str().unwrap_or_default().to_string(),
+                    hash_algo,
+                    hash: output["hash"].as_str().unwrap_or_default().to_string(),
+                }
+            })
+            .collect();
+
+        let env = value["env"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect();
+
+        Some(Derivation { outputs, env })
+    }
+
+    pub fn read(drv_path: &Path) -> Result<Derivation> {
+        let drv = fs::read(store::physical(drv_path))
+            .context(format!("Reading derivation {}", drv_path.display()))?;
+
+        Derivation::parse(&drv).context(format!("Parsing derivation {}", drv_path.display()))
+    }
+
+    /// Floating content-addressed and impure outputs have a hash algorithm but no fixed hash or
+    /// path, so only outputs with both count as fixed
+    pub fn is_fixed_output(&self) -> bool {
+        !self.outputs.is_empty()
+            && self.outputs.iter().all(|output| {
+                !output.path.is_empty() && !output.hash.is_empty() && output.hash != "impure"
+            })
+    }
+
+    pub fn urls(&self) -> Vec<String> {
+        let mut urls = Vec::new();
+
+        for var in ["urls", "url"] {
+            if let Some(value) = self.env.get(var) {
+                for url in value.split_whitespace() {
+                    if !urls.iter().any(|u| u == url) {
+                        urls.push(url.to_string());
+                    }
+                }
+            }
+        }
+
+        urls
+    }
+}
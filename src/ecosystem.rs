@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use crate::drv::Derivation;
+
+/// Language ecosystem a FOD vendors the dependencies of, whose output depends on the version of
+/// the tool resolving them as much as on upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ecosystem {
+    /// fetchCargoVendor and fetchCargoTarball
+    Cargo,
+    /// The go-modules FOD of buildGoModule
+    Go,
+    /// fetchNpmDeps
+    Npm,
+    /// fetchYarnDeps
+    Yarn,
+    /// Composer repositories and vendor directories of PHP packages
+    Composer,
+}
+
+pub const ECOSYSTEMS: &[Ecosystem] = &[
+    Ecosystem::Cargo,
+    Ecosystem::Go,
+    Ecosystem::Npm,
+    Ecosystem::Yarn,
+    Ecosystem::Composer,
+];
+
+impl Ecosystem {
+    /// Stable name for the ecosystem in machine-readable output and tags
+    pub fn name(self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => "cargo-vendor",
+            Ecosystem::Go => "go-modules",
+            Ecosystem::Npm => "npm-deps",
+            Ecosystem::Yarn => "yarn-deps",
+            Ecosystem::Composer => "composer-deps",
+        }
+    }
+
+    fn lockfile(self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => "Cargo.lock",
+            Ecosystem::Go => "go.sum",
+            Ecosystem::Npm => "package-lock.json",
+            Ecosystem::Yarn => "yarn.lock",
+            Ecosystem::Composer => "composer.lock",
+        }
+    }
+
+    /// Name of the tool's package among the FOD's inputs
+    fn tool(self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => "cargo",
+            Ecosystem::Go => "go",
+            Ecosystem::Npm | Ecosystem::Yarn => "nodejs",
+            Ecosystem::Composer => "composer",
+        }
+    }
+}
+
+/// Which ecosystem a FOD vendors dependencies of, told apart by the names and attributes the
+/// vendoring functions give their derivations
+pub fn classify(derivation: &Derivation) -> Option<Ecosystem> {
+    let env = &derivation.env;
+    let has = |var: &str| env.contains_key(var);
+    let name = env.get("name").map(String::as_str).unwrap_or_default();
+
+    if name.ends_with("-vendor.tar.gz")
+        || name.ends_with("-vendor-staging")
+        || has("cargoUpdateHook")
+    {
+        Some(Ecosystem::Cargo)
+    } else if name.ends_with("-go-modules") || (has("proxyVendor") && has("modRoot")) {
+        Some(Ecosystem::Go)
+    } else if name.ends_with("-npm-deps") {
+        Some(Ecosystem::Npm)
+    } else if name.ends_with("-yarn-deps") || has("yarnLock") {
+        Some(Ecosystem::Yarn)
+    } else if name.ends_with("-composer-repository")
+        || name.ends_with("-composer-vendor")
+        || has("composerLock")
+    {
+        Some(Ecosystem::Composer)
+    } else {
+        None
+    }
+}
+
+/// Version of a package from its store path name, as in `/nix/store/<hash>-go-1.22.3`
+fn version<'a>(path: &'a str, package: &str) -> Option<&'a str> {
+    let name = Path::new(path).file_name()?.to_str()?.split_once('-')?.1;
+
+    name.strip_prefix(package)?
+        .strip_prefix('-')
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Lockfile the dependencies were resolved from and the version of the tool that resolved them,
+/// as the version differing from the one the hash was taken with is the usual reason for a
+/// mismatch
+pub fn lockfile_context(derivation: &Derivation, ecosystem: Ecosystem) -> Option<String> {
+    let env = &derivation.env;
+    let mut context = Vec::new();
+
+    // fetchYarnDeps is given the lockfile itself rather than the source it is in
+    if let Some(lockfile) = env.get("yarnLock").filter(|path| path.starts_with('/')) {
+        context.push(format!("lockfile {}", lockfile));
+    } else if let Some(src) = env.get("src").filter(|src| src.starts_with('/')) {
+        let root = env
+            .get("modRoot")
+            .or(env.get("cargoRoot"))
+            .filter(|root| !root.is_empty());
+        let lockfile = match root {
+            Some(root) => Path::new(src).join(root).join(ecosystem.lockfile()),
+            None => Path::new(src).join(ecosystem.lockfile()),
+        };
+        context.push(format!("lockfile {}", lockfile.display()));
+    }
+
+    let tool = env
+        .get("nativeBuildInputs")
+        .into_iter()
+        .flat_map(|inputs| inputs.split_whitespace())
+        .find_map(|input| version(input, ecosystem.tool()));
+    if let Some(tool_version) = tool {
+        context.push(format!("{} {}", ecosystem.tool(), tool_version));
+    }
+
+    (!context.is_empty()).then(|| context.join(", "))
+}
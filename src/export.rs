@@ -0,0 +1,72 @@
+use serde_json::{json, Value};
+
+use crate::inventory::csv_field;
+use crate::report::{self, FodResult, Report};
+
+/// Results with failures of the same URLs for the same hash collapsed into the first, as in the
+/// text reports
+fn collapsed(report: &Report) -> Vec<(&FodResult, Vec<&FodResult>)> {
+    report::collapse(&report.results.iter().collect::<Vec<&FodResult>>())
+}
+
+/// Report as JSON, with every result and the run's summary counts, and the attrs a collapsed
+/// failure also affects under `also_affects`
+pub fn json(report: &Report) -> String {
+    format!(
+        "{}\n",
+        json!({
+            "revision": report.revision,
+            "store": report.store,
+            "summary": report::summary(&report.results),
+            "skipped": report.skipped,
+            "downloaded": report.downloaded,
+            "results": collapsed(report)
+                .into_iter()
+                .map(|(result, also)| {
+                    let mut value = result.to_json();
+                    value["also_affects"] = also
+                        .iter()
+                        .map(|other| {
+                            json!({
+                                "attr": other.attr,
+                                "drv": other.drv,
+                                "output": other.output,
+                            })
+                        })
+                        .collect::<Value>();
+                    value
+                })
+                .collect::<Vec<_>>(),
+        })
+    )
+}
+
+/// Report as CSV with a row for each result, with the tags and the attrs a collapsed failure also
+/// affects space-separated
+pub fn csv(report: &Report) -> String {
+    let mut csv =
+        "attr,drv,output,fetcher,status,expected_hash,got_hash,nar_hash,tags,also_affects\n"
+            .to_string();
+
+    for (result, also) in collapsed(report) {
+        let also = also
+            .iter()
+            .map(|other| other.attr.as_str())
+            .collect::<Vec<&str>>();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{}\n",
+            csv_field(&result.attr),
+            csv_field(&result.drv.display().to_string()),
+            csv_field(&result.output),
+            result.fetcher,
+            result.status.name(),
+            csv_field(&result.expected_hash),
+            csv_field(result.observed_hash().unwrap_or_default()),
+            csv_field(result.nar_hash.as_deref().unwrap_or_default()),
+            csv_field(&result.tags().join(" ")),
+            csv_field(&also.join(" "))
+        ));
+    }
+
+    csv
+}
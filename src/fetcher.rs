@@ -0,0 +1,68 @@
+use crate::drv::Derivation;
+use crate::http;
+
+/// Fetcher families FODs are classified into
+pub const FETCHERS: &[&str] = &[
+    "fetchurl",
+    "fetchzip",
+    "fetchpatch",
+    "fetchFromGitHub",
+    "fetchFromGitLab",
+    "fetchgit",
+    "fetchsvn",
+    "fetchhg",
+    "other",
+];
+
+fn forge(urls: &[String]) -> Option<&'static str> {
+    match http::host(urls.first()?)?.as_str() {
+        "github.com" | "codeload.github.com" => Some("fetchFromGitHub"),
+        "gitlab.com" => Some("fetchFromGitLab"),
+        _ => None,
+    }
+}
+
+/// Which fetcher a FOD most likely came from, told apart by the attributes each one passes on to
+/// its derivation
+pub fn classify(derivation: &Derivation, urls: &[String]) -> &'static str {
+    let env = &derivation.env;
+    let has = |var: &str| env.contains_key(var);
+    let post_fetch = env.get("postFetch").map(String::as_str).unwrap_or_default();
+
+    if has("leaveDotGit") || has("fetchSubmodules") || has("deepClone") {
+        forge(urls).unwrap_or("fetchgit")
+    } else if has("ignoreExternals") {
+        "fetchsvn"
+    } else if has("fetchSubrepos") {
+        "fetchhg"
+    } else if env.get("builder").map(String::as_str) == Some("builtin:fetchurl")
+        || has("urls")
+        || has("curlOpts")
+    {
+        if post_fetch.contains("lsdiff") || post_fetch.contains("filterdiff") {
+            "fetchpatch"
+        } else if post_fetch.contains("unpackFile") {
+            forge(urls).unwrap_or("fetchzip")
+        } else {
+            "fetchurl"
+        }
+    } else {
+        "other"
+    }
+}
+
+/// Whether a FOD seeds the standard environment, like the bootstrap tools every package ends up
+/// depending on
+pub fn is_bootstrap(derivation: &Derivation, urls: &[String]) -> bool {
+    let name = derivation
+        .env
+        .get("name")
+        .map(String::as_str)
+        .unwrap_or_default();
+
+    // Bootstrap files are fetched through <nix/fetchurl.nix> from the stdenv tarballs
+    urls.iter()
+        .any(|url| url.contains("tarballs.nixos.org/stdenv"))
+        || (derivation.env.get("builder").map(String::as_str) == Some("builtin:fetchurl")
+            && (name.contains("bootstrap") || name == "busybox" || name == "unpack"))
+}
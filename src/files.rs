@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::{Component, Path};
+
+use crate::html::escape;
+use crate::server::{Request, Response};
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") | Some("jsonl") => "application/json",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("css") => "text/css",
+        Some("patch") | Some("txt") | Some("csv") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Page linking to every report in a directory, newest first
+fn index(dir: &Path, url_path: &str) -> Option<Response> {
+    let mut entries = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let modified = entry.metadata().and_then(|meta| meta.modified()).ok();
+            let is_dir = entry.path().is_dir();
+            (!name.starts_with('.')).then_some((modified, name, is_dir))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.cmp(a));
+
+    let base = url_path.trim_end_matches('/');
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>FOD reports{}</title>\n</head>\n<body>\n<h1>FOD reports{}</h1>\n<ul>\n",
+        escape(base),
+        escape(base)
+    );
+    for (_, name, is_dir) in entries {
+        let name = if is_dir { format!("{}/", name) } else { name };
+        html.push_str(&format!(
+            "<li><a href=\"{}/{}\">{}</a></li>\n",
+            escape(base),
+            escape(&name),
+            escape(&name)
+        ));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+
+    Some(Response {
+        status: 200,
+        content_type: "text/html; charset=utf-8",
+        body: html.into_bytes(),
+    })
+}
+
+/// Serves the files in `dir`, with an index page for each directory
+pub fn handle(dir: &Path, request: &Request) -> Response {
+    let relative = Path::new(request.path.trim_start_matches('/'));
+
+    // Only plain names, so requests cannot reach outside the directory
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return Response::not_found();
+    }
+
+    let path = dir.join(relative);
+
+    if path.is_dir() {
+        return index(&path, &request.path).unwrap_or_else(Response::not_found);
+    }
+
+    match fs::read(&path) {
+        Ok(body) => Response {
+            status: 200,
+            content_type: content_type(&path),
+            body,
+        },
+        Err(_) => Response::not_found(),
+    }
+}
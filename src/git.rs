@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use tempfile::{tempdir, TempDir};
+
+/// Runs git in `dir`, returning its trimmed stdout
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Running git")?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.iter()
+                .find(|arg| !arg.starts_with('-') && !arg.contains('='))
+                .unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Commit a Nixpkgs checkout is at, if it is a git checkout at all
+pub fn revision(dir: &Path) -> Option<String> {
+    git(dir, &["rev-parse", "HEAD"]).ok()
+}
+
+/// Detached checkout of another revision next to the main one, removed again when dropped
+pub struct Worktree {
+    repo: PathBuf,
+    _dir: TempDir,
+    pub path: PathBuf,
+}
+
+impl Worktree {
+    pub fn add(repo: &Path, reference: &str) -> Result<Worktree> {
+        let dir = tempdir().context("Creating temporary directory for worktree")?;
+        let path = dir.path().join("nixpkgs");
+
+        git(
+            repo,
+            &[
+                "worktree",
+                "add",
+                "--detach",
+                path.to_str().expect("Path to string"),
+                reference,
+            ],
+        )
+        .context(format!("Checking out {}", reference))?;
+
+        Ok(Worktree {
+            repo: repo.to_owned(),
+            _dir: dir,
+            path,
+        })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let path = self.path.to_str().expect("Path to string");
+
+        if let Err(err) = git(&self.repo, &["worktree", "remove", "--force", path]) {
+            warn!("Error removing worktree {}, ignoring: {:#}", path, err);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::env;
+
+use anyhow::{Context, Result};
+
+use crate::http;
+
+const GITHUB_API: &str = "https://api.github.com";
+
+pub const NIXPKGS_REPO: &str = "NixOS/nixpkgs";
+
+pub fn token() -> Result<String> {
+    env::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .context("GITHUB_TOKEN is not set")
+}
+
+/// Header authenticating with the token, which `http::post` hands to curl in a file so that, as
+/// with the credential helper git pushes with, it never appears in arguments
+fn authorization() -> Result<String> {
+    Ok(format!("Authorization: Bearer {}", token()?))
+}
+
+/// Opens a draft pull request and returns its URL
+pub fn create_draft_pull(
+    repo: &str,
+    head: &str,
+    base: &str,
+    title: &str,
+    body: &str,
+) -> Result<String> {
+    let response = http::post_json(
+        &format!("{}/repos/{}/pulls", GITHUB_API, repo),
+        &[authorization()?],
+        Some(&serde_json::json!({
+            "title": title,
+            "head": head,
+            "base": base,
+            "body": body,
+            "draft": true,
+            "maintainer_can_modify": true,
+        })),
+    )?;
+
+    response["html_url"]
+        .as_str()
+        .map(String::from)
+        .ok_or(anyhow!("No pull request URL in GitHub response"))
+}
@@ -0,0 +1,175 @@
+const BASE32: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A digest in the way Nix and Nixpkgs write hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Sri,
+    Base32,
+    PrefixedBase32,
+    Hex,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hash {
+    pub algo: String,
+    pub digest: Vec<u8>,
+}
+
+fn digest_size(algo: &str) -> Option<usize> {
+    match algo {
+        "md5" => Some(16),
+        "sha1" => Some(20),
+        "sha256" => Some(32),
+        "sha512" => Some(64),
+        _ => None,
+    }
+}
+
+fn hex_encode(digest: &[u8]) -> String {
+    digest.iter().map(|c| format!("{:02x}", c)).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Nix's base32 runs backwards over the digest and skips e, o, u and t
+fn base32_encode(digest: &[u8]) -> String {
+    let len = (digest.len() * 8 - 1) / 5 + 1;
+
+    (0..len)
+        .rev()
+        .map(|n| {
+            let (i, j) = (n * 5 / 8, n * 5 % 8);
+            let low = digest[i] >> j;
+            let high = digest
+                .get(i + 1)
+                .map(|&c| (c as u16) << (8 - j))
+                .unwrap_or(0);
+            BASE32[((low as u16 | high) & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
+fn base32_decode(base32: &str, size: usize) -> Option<Vec<u8>> {
+    if base32.len() != (size * 8 - 1) / 5 + 1 {
+        return None;
+    }
+
+    let mut digest = vec![0u8; size];
+    for (n, c) in base32.bytes().rev().enumerate() {
+        let value = BASE32.iter().position(|&b| b == c)? as u16;
+        let (i, j) = (n * 5 / 8, n * 5 % 8);
+
+        digest[i] |= (value << j) as u8;
+        let carry = value >> (8 - j);
+        if i + 1 < size {
+            digest[i + 1] |= carry as u8;
+        } else if carry != 0 {
+            return None;
+        }
+    }
+
+    Some(digest)
+}
+
+fn base64_encode(digest: &[u8]) -> String {
+    let mut base64 = String::new();
+
+    for chunk in digest.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &c)| bits | (c as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                base64.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                base64.push('=');
+            }
+        }
+    }
+
+    base64
+}
+
+fn base64_decode(base64: &str) -> Option<Vec<u8>> {
+    let mut digest = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in base64.trim_end_matches('=').bytes() {
+        bits = bits << 6 | BASE64.iter().position(|&b| b == c)? as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            digest.push((bits >> count) as u8);
+        }
+    }
+
+    Some(digest)
+}
+
+impl Hash {
+    /// Parses a hash as found in a derivation, a Nix error message or a Nixpkgs expression, using
+    /// `algo` for encodings that do not name their algorithm
+    pub fn parse(hash: &str, algo: Option<&str>) -> Option<Hash> {
+        if let Some((algo, base64)) = hash.split_once('-') {
+            let digest = base64_decode(base64)?;
+            return (digest_size(algo)? == digest.len()).then(|| Hash {
+                algo: algo.to_string(),
+                digest,
+            });
+        }
+
+        let (algo, value) = match hash.split_once(':') {
+            Some((algo, value)) => (algo, value),
+            None => (algo?, hash),
+        };
+        let algo = algo.trim_start_matches("r:").trim_start_matches("text:");
+        let size = digest_size(algo)?;
+
+        let digest = if value.len() == size * 2 {
+            hex_decode(value)?
+        } else {
+            base32_decode(value, size)?
+        };
+
+        Some(Hash {
+            algo: algo.to_string(),
+            digest,
+        })
+    }
+
+    pub fn encode(&self, encoding: Encoding) -> String {
+        match encoding {
+            Encoding::Sri => format!("{}-{}", self.algo, base64_encode(&self.digest)),
+            Encoding::Base32 => base32_encode(&self.digest),
+            Encoding::PrefixedBase32 => format!("{}:{}", self.algo, base32_encode(&self.digest)),
+            Encoding::Hex => hex_encode(&self.digest),
+        }
+    }
+
+    /// Every way this hash could be spelled, most specific first so longer forms match before
+    /// the bare digests they contain
+    pub fn spellings(&self) -> Vec<(Encoding, String)> {
+        [
+            Encoding::Sri,
+            Encoding::PrefixedBase32,
+            Encoding::Base32,
+            Encoding::Hex,
+        ]
+        .into_iter()
+        .map(|encoding| (encoding, self.encode(encoding)))
+        .collect()
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use serde_json::{json, Value};
+
+use crate::diagnose::Reachability;
+use crate::report::{FodResult, Status};
+use crate::state::State;
+
+/// A FOD output's result as kept in the history
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub attr: String,
+    pub drv: PathBuf,
+    pub output: String,
+    pub status: Status,
+    /// Fetcher the FOD uses, missing from records made before it was kept
+    pub fetcher: Option<String>,
+    pub expected_hash: String,
+    pub got_hash: Option<String>,
+    pub nar_hash: Option<String>,
+    /// Whether it only passed on a retry
+    pub flaky: bool,
+    /// How far upstream could be reached after the fetch failed
+    pub reachability: Option<Reachability>,
+    /// Whether Nix fetched it in the build sandbox
+    pub sandboxed: Option<bool>,
+    pub state: Option<State>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Run {
+    /// Unix time the run started at, which also identifies it
+    pub started: u64,
+    pub finished: u64,
+    pub revision: Option<String>,
+    /// Store URI the run's Nix commands were given
+    pub store: Option<String>,
+    pub records: Vec<Record>,
+}
+
+/// Runs recorded so far, kept as one JSON object per line so a run is only ever appended
+pub struct History {
+    path: PathBuf,
+    pub runs: Vec<Run>,
+    /// Triage tags assigned to attrs by hand, from tag lines between the runs
+    tags: BTreeMap<String, BTreeSet<String>>,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+impl Record {
+    pub fn new(result: &FodResult) -> Record {
+        Record {
+            attr: result.attr.clone(),
+            drv: result.drv.clone(),
+            output: result.output.clone(),
+            status: result.status,
+            fetcher: Some(result.fetcher.to_string()),
+            expected_hash: result.expected_hash.clone(),
+            got_hash: result.got_hash.clone(),
+            nar_hash: result.nar_hash.clone(),
+            flaky: result.flaky,
+            reachability: result.reachability,
+            sandboxed: result.sandboxed,
+            state: result.state,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "attr": self.attr,
+            "drv": self.drv,
+            "output": self.output,
+            "status": self.status.name(),
+            "fetcher": self.fetcher,
+            "expected_hash": self.expected_hash,
+            "got_hash": self.got_hash,
+            "nar_hash": self.nar_hash,
+            "flaky": self.flaky,
+            "reachability": self.reachability.map(Reachability::name),
+            "sandboxed": self.sandboxed,
+            "state": self.state.map(State::name),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Record> {
+        Some(Record {
+            attr: value["attr"].as_str()?.to_string(),
+            drv: PathBuf::from(value["drv"].as_str()?),
+            output: value["output"].as_str()?.to_string(),
+            status: Status::from_name(value["status"].as_str()?)?,
+            fetcher: value["fetcher"].as_str().map(String::from),
+            expected_hash: value["expected_hash"].as_str()?.to_string(),
+            got_hash: value["got_hash"].as_str().map(String::from),
+            nar_hash: value["nar_hash"].as_str().map(String::from),
+            flaky: value["flaky"].as_bool().unwrap_or(false),
+            reachability: value["reachability"]
+                .as_str()
+                .and_then(Reachability::from_name),
+            sandboxed: value["sandboxed"].as_bool(),
+            state: value["state"].as_str().and_then(State::from_name),
+        })
+    }
+}
+
+impl Run {
+    /// Content a mismatching output got in this run, when `result` now got different content for
+    /// the same expected hash, meaning upstream changed what it serves more than once
+    pub fn retagged(&self, result: &FodResult) -> Option<&str> {
+        let got = result.got_hash.as_deref()?;
+
+        self.records
+            .iter()
+            .find(|record| {
+                record.attr == result.attr
+                    && record.output == result.output
+                    && record.expected_hash == result.expected_hash
+            })
+            .and_then(|record| record.got_hash.as_deref())
+            .filter(|previous| *previous != got)
+    }
+
+    pub fn new(
+        started: u64,
+        revision: Option<String>,
+        store: Option<String>,
+        results: &[FodResult],
+    ) -> Run {
+        Run {
+            started,
+            finished: now(),
+            revision,
+            store,
+            records: results.iter().map(Record::new).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "started": self.started,
+            "finished": self.finished,
+            "revision": self.revision,
+            "store": self.store,
+            "records": self.records.iter().map(Record::to_json).collect::<Vec<Value>>(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Run> {
+        Some(Run {
+            started: value["started"].as_u64()?,
+            finished: value["finished"].as_u64()?,
+            revision: value["revision"].as_str().map(String::from),
+            store: value["store"].as_str().map(String::from),
+            records: value["records"]
+                .as_array()?
+                .iter()
+                .filter_map(Record::from_json)
+                .collect(),
+        })
+    }
+}
+
+impl History {
+    pub fn load(path: &Path) -> Result<History> {
+        let mut history = History {
+            path: path.to_owned(),
+            runs: Vec::new(),
+            tags: BTreeMap::new(),
+        };
+
+        if !path.try_exists().unwrap_or(false) {
+            return Ok(history);
+        }
+
+        let contents = fs::read_to_string(path).context("Reading history file")?;
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let value = serde_json::from_str::<Value>(line).context("Deserializing history")?;
+            if let (Some(attr), Some(tag)) = (value["attr"].as_str(), value["tag"].as_str()) {
+                history.apply_tag(attr, tag, value["removed"].as_bool().unwrap_or(false));
+                continue;
+            }
+
+            history
+                .runs
+                .push(Run::from_json(&value).ok_or(anyhow!("Invalid run on line {}", i + 1))?);
+        }
+
+        Ok(history)
+    }
+
+    fn write_line(&self, value: &Value) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Opening history file")?;
+
+        writeln!(file, "{}", value).context("Writing history file")
+    }
+
+    pub fn append(&mut self, run: Run) -> Result<()> {
+        self.write_line(&run.to_json())?;
+
+        self.runs.push(run);
+        Ok(())
+    }
+
+    fn apply_tag(&mut self, attr: &str, tag: &str, removed: bool) {
+        let tags = self.tags.entry(attr.to_string()).or_default();
+        if removed {
+            tags.remove(tag);
+        } else {
+            tags.insert(tag.to_string());
+        }
+    }
+
+    /// Adds or removes a triage tag on an attr, recording the change after the runs so far
+    pub fn tag(&mut self, attr: &str, tag: &str, removed: bool) -> Result<()> {
+        self.write_line(&json!({
+            "attr": attr,
+            "tag": tag,
+            "removed": removed,
+            "at": now(),
+        }))?;
+
+        self.apply_tag(attr, tag, removed);
+        Ok(())
+    }
+
+    pub fn tags(&self, attr: &str) -> Vec<String> {
+        self.tags.get(attr).into_iter().flatten().cloned().collect()
+    }
+
+    pub fn latest(&self) -> Option<&Run> {
+        self.runs.last()
+    }
+}
@@ -0,0 +1,223 @@
+use crate::report::{self, FodResult, Report, Status};
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn code(text: Option<&str>) -> String {
+    text.map(|text| format!("<code>{}</code>", escape(text)))
+        .unwrap_or_default()
+}
+
+/// Fragment linking to a failure class's section
+fn anchor(class: &str) -> String {
+    class.to_lowercase().replace(' ', "-")
+}
+
+fn table(results: &[&FodResult]) -> String {
+    let mut table =
+        "<table>\n<tr><th>Attr</th><th>Derivation</th><th>Fetcher</th><th>Tags</th><th>Expected</th><th>Got</th><th>NAR hash</th><th>Status</th></tr>\n"
+            .to_string();
+
+    for (result, also) in report::collapse(results) {
+        let mut attr = format!("<code>{}</code>", escape(&result.attr));
+        if let Some(lockfile) = &result.lockfile {
+            attr.push_str(&format!("<br>vendored with {}", escape(lockfile)));
+        }
+        if !also.is_empty() {
+            attr.push_str(&format!(
+                "<br>also affects {}",
+                escape(&report::also_affects(&also))
+            ));
+        }
+
+        table.push_str(&format!(
+            "<tr class=\"{}\"><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            if result.status.is_failure() {
+                "failure"
+            } else {
+                "skipped"
+            },
+            attr,
+            escape(&result.target()),
+            result.fetcher,
+            escape(&result.tags().join(", ")),
+            code(Some(&result.expected_hash)),
+            code(result.observed_hash()),
+            code(result.nar_hash.as_deref()),
+            result.status.description()
+        ));
+    }
+
+    table.push_str("</table>\n");
+    table
+}
+
+/// Standalone HTML report with a collapsible section for each package set
+pub fn render(report: &Report) -> String {
+    let results = &report.results;
+    let mut html = format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>FOD reproducibility report</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; }}
+td, th {{ border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; }}
+tr.failure td:last-child {{ color: #b00; }}
+summary {{ cursor: pointer; margin: 0.5em 0; }}
+</style>
+</head>
+<body>
+<h1>FOD reproducibility report</h1>
+<p>{}</p>
+",
+        escape(&report::summary(results))
+    );
+    if let Some(states) = report::states(results) {
+        html.push_str(&format!("<p>States: {}</p>\n", escape(&states)));
+    }
+    if let Some(ecosystems) = report::ecosystems(results) {
+        html.push_str(&format!("<p>Ecosystems: {}</p>\n", escape(&ecosystems)));
+    }
+    if let Some(bandwidth) = report::bandwidth(report) {
+        html.push_str(&format!("<p>Bandwidth: {}</p>\n", escape(&bandwidth)));
+    }
+
+    let reported = report::reported(results);
+    let by_reason = report::by_reason(&reported);
+    let eval_failures = report::eval_failures(report);
+    let mut sections = by_reason
+        .iter()
+        .map(|(class, members)| (*class, members.len()))
+        .collect::<Vec<(&str, usize)>>();
+    if !eval_failures.is_empty() {
+        sections.push(("Eval failure", eval_failures.len()));
+    }
+    if !sections.is_empty() {
+        html.push_str("<ul>\n");
+        for (class, count) in &sections {
+            html.push_str(&format!(
+                "<li><a href=\"#{}\">{}</a>: {}</li>\n",
+                anchor(class),
+                class,
+                count
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+    for (class, members) in &by_reason {
+        html.push_str(&format!(
+            "<h2 id=\"{}\">{} ({})</h2>\n",
+            anchor(class),
+            class,
+            members.len()
+        ));
+        html.push_str(&table(members));
+    }
+    if !eval_failures.is_empty() {
+        html.push_str(&format!(
+            "<h2 id=\"{}\">Eval failure ({})</h2>\n<ul>\n",
+            anchor("Eval failure"),
+            eval_failures.len()
+        ));
+        for attr in eval_failures {
+            html.push_str(&format!("<li><code>{}</code></li>\n", escape(attr)));
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("<h2>By package set</h2>\n");
+
+    for (group, members) in report::groups(results) {
+        let unreproducible = members
+            .iter()
+            .copied()
+            .filter(|result| {
+                result.status != Status::Reproducible
+                    && result.known_issue.is_none()
+                    && !result.quarantined
+            })
+            .collect::<Vec<&FodResult>>();
+        let failing = unreproducible
+            .iter()
+            .filter(|result| result.status.is_failure())
+            .count();
+
+        html.push_str(&format!(
+            "<details>\n<summary><b>{}</b>: {} of {} FOD outputs failing</summary>\n",
+            escape(&group),
+            failing,
+            members.len()
+        ));
+        if unreproducible.is_empty() {
+            html.push_str("<p>All reproducible.</p>\n");
+        } else {
+            html.push_str(&table(&unreproducible));
+        }
+        html.push_str("</details>\n");
+    }
+
+    let coverage = report::coverage(report);
+    if !coverage.is_empty() {
+        html.push_str(&format!(
+            "<h2>Coverage</h2>\n<p>{} of {} FOD outputs checked, left out:</p>\n<ul>\n",
+            report::checked(results),
+            results.len()
+        ));
+        for (reason, count) in coverage {
+            html.push_str(&format!("<li>{}: {}</li>\n", reason, count));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    let by_revision = report::by_revision(results);
+    if !by_revision.is_empty() {
+        html.push_str("<h2>Per revision</h2>\n<ul>\n");
+        for (revision, results) in by_revision {
+            html.push_str(&format!(
+                "<li><code>{}</code>: {}</li>\n",
+                escape(revision),
+                escape(&report::summary(results))
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    let alias_only = report::alias_only(results);
+    if !alias_only.is_empty() {
+        html.push_str(&format!(
+            "<h2>Only reachable through aliases ({})</h2>\n",
+            alias_only.len()
+        ));
+        html.push_str(&table(&alias_only));
+    }
+
+    let quarantined = report::quarantined(results);
+    if !quarantined.is_empty() {
+        html.push_str("<h2>Quarantined as flaky</h2>\n");
+        html.push_str(&table(&quarantined));
+    }
+
+    let known = report::known_issues(results);
+    if !known.is_empty() {
+        html.push_str("<h2>Known issues</h2>\n<table>\n<tr><th>Attr</th><th>Derivation</th><th>Status</th><th>Reason</th></tr>\n");
+        for result in known {
+            html.push_str(&format!(
+                "<tr><td><code>{}</code></td><td><code>{}</code></td><td>{}</td><td>{}</td></tr>\n",
+                escape(&result.attr),
+                escape(&result.target()),
+                result.status.description(),
+                escape(result.known_issue.as_deref().unwrap_or_default())
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
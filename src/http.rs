@@ -0,0 +1,223 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use anyhow::{Context, Result};
+use tempfile::NamedTempFile;
+
+const STATUS_MARKER: &str = "\n%{http_code}";
+
+/// Seconds to wait for a connection, which stalled servers would otherwise hold jobs up on
+const CONNECT_TIMEOUT: &str = "30";
+
+/// Seconds a request may take in all, for everything but downloads
+const MAX_TIME: &str = "120";
+
+/// Seconds a download may go without receiving anything before it is given up on, as downloads
+/// take as long as their size needs
+const STALL_TIME: &str = "60";
+
+/// Runs a request that may take up to `MAX_TIME`, unless `args` give a `--max-time` of their own
+fn curl(args: &[&str]) -> Result<(u32, Vec<u8>)> {
+    curl_with_stdin(&[&["--max-time", MAX_TIME], args].concat(), None)
+}
+
+/// Runs curl with `stdin` as its standard input, for request bodies too large or too private to
+/// pass as an argument
+fn curl_with_stdin(args: &[&str], stdin: Option<&[u8]>) -> Result<(u32, Vec<u8>)> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--location", "--retry", "2"])
+        .args(["--connect-timeout", CONNECT_TIMEOUT])
+        .args(["--write-out", STATUS_MARKER])
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Running curl")?;
+
+    let pipe = child.stdin.take();
+    let output = thread::scope(|scope| {
+        if let (Some(mut pipe), Some(stdin)) = (pipe, stdin) {
+            // Written alongside reading the output so neither side waits on a full pipe
+            scope.spawn(move || pipe.write_all(stdin));
+        }
+        child.wait_with_output()
+    })
+    .context("Running curl")?;
+
+    if !output.status.success() {
+        bail!(
+            "curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let mut body = output.stdout;
+    let split = body
+        .iter()
+        .rposition(|&c| c == b'\n')
+        .ok_or(anyhow!("No HTTP status in curl output"))?;
+    let status = String::from_utf8_lossy(&body[split + 1..])
+        .trim()
+        .parse()
+        .context("Parsing HTTP status")?;
+    body.truncate(split);
+
+    Ok((status, body))
+}
+
+pub fn encode(component: &str) -> String {
+    component
+        .bytes()
+        .map(|c| match c {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (c as char).to_string()
+            }
+            _ => format!("%{:02X}", c),
+        })
+        .collect()
+}
+
+pub fn head(url: &str) -> Result<u32> {
+    let (status, _) =
+        curl(&["--head", "--max-time", "30", url]).context(format!("Checking {}", url))?;
+
+    Ok(status)
+}
+
+pub fn get(url: &str) -> Result<Option<Vec<u8>>> {
+    get_with(url, &[])
+}
+
+/// Downloads the body straight to `path` rather than into memory, for files as large as the
+/// sources FODs fetch, returning false when the URL is gone
+pub fn download(url: &str, path: &Path) -> Result<bool> {
+    let args = [
+        "--speed-limit",
+        "1",
+        "--speed-time",
+        STALL_TIME,
+        "--output",
+        path.to_str().expect("Path to string"),
+        url,
+    ];
+
+    match curl_with_stdin(&args, None).context(format!("Downloading {}", url))? {
+        (200..=299, _) => Ok(true),
+        (404 | 410, _) => Ok(false),
+        (status, _) => Err(anyhow!("Downloading {} returned HTTP {}", url, status)),
+    }
+}
+
+pub fn get_json(url: &str) -> Result<Option<serde_json::Value>> {
+    get_with(url, &["--header", "Accept: application/json"])?
+        .map(|body| serde_json::from_slice(&body).context(format!("Parsing JSON from {}", url)))
+        .transpose()
+}
+
+/// Posts `body` as JSON, passing it on stdin and `headers` in a file only we can read so neither
+/// ends up in curl's arguments, where they would be too long or visible to every local user
+pub fn post(url: &str, headers: &[String], body: Option<&serde_json::Value>) -> Result<Vec<u8>> {
+    let mut header_file = NamedTempFile::new().context("Creating temporary file for headers")?;
+    for header in headers {
+        writeln!(header_file, "{}", header).context("Writing headers")?;
+    }
+    let header_arg = format!("@{}", header_file.path().to_str().expect("Path to string"));
+
+    let mut args = vec![
+        "--max-time",
+        MAX_TIME,
+        "--request",
+        "POST",
+        "--header",
+        "Accept: application/json",
+        "--header",
+        &header_arg,
+    ];
+    let body = body.map(|body| body.to_string());
+    if body.is_some() {
+        args.extend([
+            "--header",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+        ]);
+    }
+    args.push(url);
+
+    match curl_with_stdin(&args, body.as_deref().map(str::as_bytes))
+        .context(format!("Posting to {}", url))?
+    {
+        (200..=299, body) => Ok(body),
+        (status, _) => Err(anyhow!("Posting to {} returned HTTP {}", url, status)),
+    }
+}
+
+pub fn post_json(
+    url: &str,
+    headers: &[String],
+    body: Option<&serde_json::Value>,
+) -> Result<serde_json::Value> {
+    serde_json::from_slice(&post(url, headers, body)?).context(format!("Parsing JSON from {}", url))
+}
+
+fn get_with(url: &str, args: &[&str]) -> Result<Option<Vec<u8>>> {
+    match curl(&[args, &[url]].concat()).context(format!("Fetching {}", url))? {
+        (200..=299, body) => Ok(Some(body)),
+        (404 | 410, _) => Ok(None),
+        (status, _) => Err(anyhow!("Fetching {} returned HTTP {}", url, status)),
+    }
+}
+
+pub fn is_alive(url: &str) -> Option<bool> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return None;
+    }
+
+    match head(url) {
+        Ok(404 | 410) => Some(false),
+        // Some servers refuse HEAD requests without the content actually being gone
+        Ok(_) => Some(true),
+        Err(_) => Some(false),
+    }
+}
+
+pub fn any_alive(urls: &[String]) -> Option<bool> {
+    let mut checked = false;
+
+    for url in urls {
+        match is_alive(url) {
+            Some(true) => return Some(true),
+            Some(false) => checked = true,
+            None => {}
+        }
+    }
+
+    checked.then_some(false)
+}
+
+pub fn host(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+
+    let host = rest
+        .split(['/', '?', '#'])
+        .next()?
+        .rsplit('@')
+        .next()?
+        .to_lowercase();
+
+    if host.is_empty() {
+        None
+    } else if scheme == "mirror" {
+        // Mirror URLs are resolved by the fetcher, so the mirror name stands in for the host
+        Some(format!("mirror://{}", host))
+    } else {
+        Some(host)
+    }
+}
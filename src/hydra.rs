@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::cache;
+use crate::http;
+
+pub const DEFAULT_HYDRA: &str = "https://hydra.nixos.org";
+
+#[derive(Debug, Clone)]
+pub struct HydraBuild {
+    pub id: u64,
+    /// NAR hash of the output Hydra serves from its binary cache, when it still has it
+    pub nar_hash: Option<String>,
+}
+
+/// Builds Hydra's search finds for a full store path, which it only matches exactly
+fn search(hydra_url: &str, path: &str) -> Result<Vec<serde_json::Value>> {
+    let Some(results) = http::get_json(&format!(
+        "{}/search?query={}",
+        hydra_url.trim_end_matches('/'),
+        http::encode(path)
+    ))?
+    else {
+        return Ok(vec![]);
+    };
+
+    Ok(["builds", "buildsdrv"]
+        .iter()
+        .filter_map(|key| results[key].as_array())
+        .flatten()
+        .cloned()
+        .collect())
+}
+
+/// Latest successful Hydra build of the derivation or its output, which for FODs is only found
+/// when one is a job of its own rather than a step of another build
+pub fn latest_success(
+    hydra_url: &str,
+    drv_path: &Path,
+    out_path: &Path,
+) -> Result<Option<HydraBuild>> {
+    let drv_path = drv_path.to_str().expect("Path to string");
+    let out_path = out_path.to_str().expect("Path to string");
+
+    let mut builds = search(hydra_url, out_path)?;
+    builds.extend(search(hydra_url, drv_path)?);
+
+    let Some(id) = builds
+        .iter()
+        .filter(|build| {
+            build["finished"].as_u64() == Some(1)
+                && build["buildstatus"].as_u64() == Some(0)
+                && (build["drvpath"].as_str() == Some(drv_path)
+                    || build["buildoutputs"]
+                        .as_object()
+                        .into_iter()
+                        .flat_map(|outputs| outputs.values())
+                        .any(|output| output["path"].as_str() == Some(out_path)))
+        })
+        .filter_map(|build| build["id"].as_u64())
+        .max()
+    else {
+        return Ok(None);
+    };
+
+    let nar_hash = cache::narinfo(hydra_url, Path::new(out_path))
+        .unwrap_or_else(|err| {
+            warn!(
+                "Error querying Hydra for the NAR hash of {}, ignoring: {:#}",
+                out_path, err
+            );
+            None
+        })
+        .map(|narinfo| narinfo.nar_hash);
+
+    Ok(Some(HydraBuild { id, nar_hash }))
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::history::now;
+use crate::report::FodResult;
+
+/// Known failure to keep out of the main report, from an `[[ignore]]` table in the ignore file
+#[derive(Debug, Default)]
+pub struct Entry {
+    attr: Option<String>,
+    /// Hash part of the derivation path, for ignoring one derivation of an attr
+    drv: Option<String>,
+    reason: String,
+    /// Date the entry stops applying on, as given and in days since the Unix epoch
+    expires: Option<(String, i64)>,
+}
+
+/// Days since the Unix epoch for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+fn date(value: &str) -> Option<i64> {
+    let mut parts = value.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts
+        .next()?
+        .parse()
+        .ok()
+        .filter(|m| (1..=12).contains(m))?;
+    let day = parts
+        .next()?
+        .parse()
+        .ok()
+        .filter(|d| (1..=31).contains(d))?;
+
+    Some(days_from_civil(year, month, day))
+}
+
+/// Value of a `key = value` line, being a basic string or a bare date
+fn value(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+
+    let Some(quoted) = raw.strip_prefix('"') else {
+        // Comments can only follow bare values here, as strings could contain #
+        return Some(raw.split('#').next()?.trim().to_string());
+    };
+
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+/// Parses the subset of TOML ignore files use: `[[ignore]]` tables of strings and dates
+pub fn parse(contents: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::<Entry>::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.split('#').next().unwrap_or_default().trim() == "[[ignore]]" {
+            entries.push(Entry::default());
+            continue;
+        }
+
+        let (key, raw) = line
+            .split_once('=')
+            .ok_or(anyhow!("Expected key = value on line {}", i + 1))?;
+        let value = value(raw).ok_or(anyhow!("Invalid value on line {}", i + 1))?;
+        let entry = entries
+            .last_mut()
+            .ok_or(anyhow!("Key outside of [[ignore]] on line {}", i + 1))?;
+
+        match key.trim() {
+            "attr" => entry.attr = Some(value),
+            "drv" => entry.drv = Some(value),
+            "reason" => entry.reason = value,
+            "expires" => {
+                let days = date(&value).ok_or(anyhow!("Invalid date on line {}", i + 1))?;
+                entry.expires = Some((value, days));
+            }
+            key => bail!("Unknown key {} on line {}", key, i + 1),
+        }
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.attr.is_none() && entry.drv.is_none() {
+            bail!("Ignore entry {} has neither attr nor drv", i + 1);
+        }
+    }
+
+    Ok(entries)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    parse(&fs::read_to_string(path).context("Reading ignore file")?)
+        .context(format!("Parsing ignore file {}", path.display()))
+}
+
+impl Entry {
+    fn matches(&self, result: &FodResult) -> bool {
+        let drv_name = result
+            .drv
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        self.attr.as_ref().is_none_or(|attr| *attr == result.attr)
+            && self
+                .drv
+                .as_ref()
+                .is_none_or(|drv| drv_name.starts_with(drv.as_str()))
+    }
+}
+
+/// Marks failures matching an unexpired entry as known issues, with the entry's reason
+pub fn apply(entries: &[Entry], results: &mut [FodResult]) {
+    let today = (now() / (24 * 60 * 60)) as i64;
+
+    for entry in entries {
+        if let Some((expires, _)) = entry.expires.as_ref().filter(|(_, days)| *days <= today) {
+            warn!(
+                "Ignore entry for {} expired on {}, reporting it again",
+                entry
+                    .attr
+                    .as_deref()
+                    .or(entry.drv.as_deref())
+                    .unwrap_or_default(),
+                expires
+            );
+        }
+    }
+
+    for result in results
+        .iter_mut()
+        .filter(|result| result.status.is_failure())
+    {
+        result.known_issue = entries
+            .iter()
+            .filter(|entry| entry.expires.as_ref().is_none_or(|(_, days)| *days > today))
+            .find(|entry| entry.matches(result))
+            .map(|entry| entry.reason.clone());
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::json;
+
+/// FODs and attrs referencing an upstream URL
+#[derive(Default)]
+pub struct Url {
+    pub fods: usize,
+    pub attrs: BTreeSet<String>,
+}
+
+pub type Inventory = BTreeMap<String, Url>;
+
+pub fn add(inventory: &mut Inventory, urls: &[String], attr: &str) {
+    for url in urls {
+        let entry = inventory.entry(url.clone()).or_default();
+        entry.fods += 1;
+        entry.attrs.insert(attr.to_string());
+    }
+}
+
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Renders the inventory as JSON or as CSV with the attrs space-separated
+pub fn render(inventory: &Inventory, format: &str) -> String {
+    match format {
+        "csv" => {
+            let mut csv = "url,fods,attrs\n".to_string();
+            for (url, entry) in inventory {
+                csv.push_str(&format!(
+                    "{},{},{}\n",
+                    csv_field(url),
+                    entry.fods,
+                    csv_field(
+                        &entry
+                            .attrs
+                            .iter()
+                            .cloned()
+                            .collect::<Vec<String>>()
+                            .join(" ")
+                    )
+                ));
+            }
+            csv
+        }
+        _ => format!(
+            "{}\n",
+            inventory
+                .iter()
+                .map(|(url, entry)| json!({
+                    "url": url,
+                    "fods": entry.fods,
+                    "attrs": entry.attrs,
+                }))
+                .collect::<serde_json::Value>()
+        ),
+    }
+}
@@ -0,0 +1,21 @@
+# Attr paths of the jobs in a Hydra jobset or other target, found the way hydra-eval-jobs does:
+# every derivation in the attrset it evaluates to, recursing into any attrset not a derivation
+{ target }:
+
+let
+  # Attr paths are passed to nix-instantiate -A, where names with dots in them need quoting
+  quote = name: if builtins.match "[a-zA-Z_][a-zA-Z0-9_'-]*" name != null then name else ''"${name}"'';
+
+  # Jobs failing to evaluate are left out, as Hydra reports them apart rather than building them
+  try = expr: let result = builtins.tryEval expr; in result.success && result.value;
+
+  walk = path: value:
+    if try (builtins.isAttrs value && value.type or null == "derivation") then
+      [ (builtins.concatStringsSep "." path) ]
+    else if try (builtins.isAttrs value) then
+      builtins.concatLists (builtins.attrValues (builtins.mapAttrs (name: walk (path ++ [ (quote name) ])) value))
+    else
+      [ ];
+
+in
+walk [ ] (if builtins.isFunction target then target { } else target)
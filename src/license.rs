@@ -0,0 +1,50 @@
+/// Filter value matching any license Nixpkgs considers free
+pub const FREE_ONLY: &str = "free-only";
+
+#[derive(Debug, Clone)]
+pub struct License {
+    pub spdx_id: Option<String>,
+    pub free: bool,
+}
+
+/// Licenses from an evaluated meta.license, which may be a single license, a list of them, or a
+/// plain string in older expressions
+pub fn parse(meta_license: &serde_json::Value) -> Vec<License> {
+    let licenses = match meta_license.as_array() {
+        Some(licenses) => licenses.iter().collect(),
+        None => vec![meta_license],
+    };
+
+    licenses
+        .into_iter()
+        .filter(|license| !license.is_null())
+        .map(|license| match license.as_str() {
+            Some(spdx_id) => License {
+                spdx_id: Some(spdx_id.to_string()),
+                free: true,
+            },
+            None => License {
+                spdx_id: license["spdxId"].as_str().map(String::from),
+                free: license["free"].as_bool().unwrap_or(true),
+            },
+        })
+        .collect()
+}
+
+/// Whether every license matches one of `filters`, which are SPDX identifiers or `free-only`
+pub fn allowed(filters: &[String], licenses: &[License]) -> bool {
+    // Nixpkgs treats packages without a license as free, but they cannot match an SPDX identifier
+    if licenses.is_empty() {
+        return filters.iter().any(|filter| filter == FREE_ONLY);
+    }
+
+    licenses.iter().all(|license| {
+        filters.iter().any(|filter| {
+            if filter == FREE_ONLY {
+                license.free
+            } else {
+                license.spdx_id.as_ref() == Some(filter)
+            }
+        })
+    })
+}
@@ -0,0 +1,61 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::drv::{Derivation, Output};
+use crate::hash::Hash;
+
+const BRANCHES: &str = "master|main|trunk|develop|devel|dev|default|stable|nightly|latest|HEAD";
+
+static BRANCH_REV: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"^(?:refs/heads/.+|(?:{}))$", BRANCHES)).unwrap());
+
+static BRANCH_ARCHIVE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"/(?:-/)?archive/(?:refs/heads/[^/]+|(?:{})(?:/[^/]*)?)\.(?:tar\.gz|tar\.bz2|tgz|zip)$",
+        BRANCHES
+    ))
+    .unwrap()
+});
+
+static LATEST_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)(?:^|[/\-_.])(?:latest|current)(?:[/\-_.]|$)").unwrap());
+
+/// Warnings for FODs pinned to references that can change under them
+pub fn unstable_references(derivation: &Derivation, urls: &[String]) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for var in ["rev", "branchName"] {
+        if let Some(rev) = derivation.env.get(var) {
+            if BRANCH_REV.is_match(rev) {
+                warnings.push(format!("{} is the mutable reference {}", var, rev));
+            }
+        }
+    }
+
+    for url in urls {
+        if BRANCH_ARCHIVE.is_match(url) {
+            warnings.push(format!("{} is an archive of a branch", url));
+        } else if LATEST_URL.is_match(url.rsplit('/').next().unwrap_or(url))
+            || url.contains("/latest/")
+        {
+            warnings.push(format!("{} refers to a moving release", url));
+        }
+    }
+
+    warnings
+}
+
+/// Warning for FOD outputs still pinned with md5 or sha1 rather than sha256/sha512
+pub fn weak_hash(output: &Output) -> Option<String> {
+    // The digest size gives the algorithm away in any encoding, even where the one named is wrong
+    let hash = Hash::parse(&output.hash, Some(&output.hash_algo))
+        .or_else(|| Hash::parse(&output.hash, Some("md5")))
+        .or_else(|| Hash::parse(&output.hash, Some("sha1")))?;
+
+    match hash.digest.len() {
+        16 => Some(format!("output hash {} uses md5", output.hash)),
+        20 => Some(format!("output hash {} uses sha1", output.hash)),
+        _ => None,
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+pub const QUIET: u8 = 0;
+pub const NORMAL: u8 = 1;
+pub const STREAMING: u8 = 3;
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(NORMAL);
+static COLOR: AtomicBool = AtomicBool::new(false);
+static OUTPUT: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    static GROUP: RefCell<Option<Vec<(bool, String)>>> = const { RefCell::new(None) };
+}
+
+/// Prints progress messages unless running quietly
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::verbosity() > $crate::log::QUIET {
+            $crate::log::emit(false, format!($($arg)*))
+        }
+    };
+}
+
+/// Prints problems that the run carries on from
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::log::emit(true, $crate::log::yellow(&format!($($arg)*)))
+    };
+}
+
+/// Prints failures
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::emit(true, $crate::log::red(&format!($($arg)*)))
+    };
+}
+
+pub fn set_verbosity(verbosity: u8) {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Whether child process output should be streamed to the terminal as it arrives
+pub fn streaming() -> bool {
+    verbosity() >= STREAMING
+}
+
+pub fn quiet() -> bool {
+    verbosity() == QUIET
+}
+
+pub fn set_color(choice: &str) {
+    let color = match choice {
+        "always" => true,
+        "never" => false,
+        _ => {
+            env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && io::stdout().is_terminal()
+        }
+    };
+
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+fn paint(code: &str, text: &str) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn red(text: &str) -> String {
+    paint("31", text)
+}
+
+pub fn yellow(text: &str) -> String {
+    paint("33", text)
+}
+
+pub fn bold(text: &str) -> String {
+    paint("1", text)
+}
+
+pub fn emit(stderr: bool, line: String) {
+    let grouped = GROUP.with(|group| match group.borrow_mut().as_mut() {
+        Some(lines) => {
+            lines.push((stderr, line.clone()));
+            true
+        }
+        None => false,
+    });
+
+    if !grouped {
+        let _guard = OUTPUT.lock().expect("Acquiring output mutex");
+        if stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Runs `f` holding back its messages, then prints them together so that output from parallel
+/// workers does not interleave
+pub fn group<T>(f: impl FnOnce() -> T) -> T {
+    if GROUP.with(|group| group.borrow().is_some()) {
+        return f();
+    }
+
+    GROUP.with(|group| *group.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let lines = GROUP
+        .with(|group| group.borrow_mut().take())
+        .unwrap_or_default();
+
+    let _guard = OUTPUT.lock().expect("Acquiring output mutex");
+    for (stderr, line) in lines {
+        if stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    result
+}
@@ -1,65 +1,367 @@
 #[macro_use]
 extern crate anyhow;
 
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::env;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Seek, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
-use std::process::{self, Command, Stdio};
-use std::sync::Mutex;
+use std::process::{self, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 
 use rayon::iter;
 use rayon::prelude::{
-    IndexedParallelIterator, IntoParallelRefIterator, ParallelExtend, ParallelIterator,
+    IndexedParallelIterator, IntoParallelRefIterator, ParallelBridge, ParallelExtend,
+    ParallelIterator,
 };
 
-use regex::bytes::Regex;
-
 use tempfile::{tempdir, tempfile};
 
-fn run(cmd: &str, args: &[&str], path: &[&Path]) -> Result<File> {
+#[macro_use]
+mod log;
+
+mod alerts;
+mod api;
+mod audit;
+mod cache;
+mod compare;
+mod content;
+mod diagnose;
+mod disk;
+mod drv;
+mod ecosystem;
+mod export;
+mod fetcher;
+mod files;
+mod git;
+mod github;
+mod hash;
+mod history;
+mod html;
+mod http;
+mod hydra;
+mod ignore;
+mod inventory;
+mod license;
+mod lint;
+mod markdown;
+mod mentions;
+mod nixlog;
+mod options;
+mod patch;
+mod plan;
+mod pr;
+mod quarantine;
+mod r13y;
+mod reclone;
+mod report;
+mod roots;
+mod schedule;
+mod server;
+mod state;
+mod store;
+mod submit;
+mod swh;
+mod systemd;
+mod timeseries;
+mod tofu;
+mod wayback;
+
+use disk::DiskMonitor;
+use drv::Derivation;
+use hash::{Encoding, Hash};
+use history::{History, Run};
+use options::{Options, USAGE};
+use report::{AttrTiming, FodResult, Report, Status};
+use roots::Roots;
+use swh::SwhStatus;
+use tofu::Tofu;
+
+/// Failure of a Nix process, keeping its exit code to tell build failures from broken setups
+#[derive(Debug)]
+struct NixFailure {
+    code: Option<i32>,
+    log: Vec<String>,
+}
+
+impl fmt::Display for NixFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(
+                f,
+                "Nix process failed with exit code {}, see above output",
+                code
+            ),
+            None => write!(f, "Nix process failed, see above output"),
+        }
+    }
+}
+
+impl std::error::Error for NixFailure {}
+
+/// Bits Nix sets in its build failure exit code for a timeout, a build failing, which includes
+/// timeouts and hash mismatches, and `--check` finding different output
+const EXIT_TIMED_OUT: i32 = 0x1;
+const EXIT_BUILD_FAILED: i32 = 0x4;
+const EXIT_CHECK_MISMATCH: i32 = 0x8;
+
+/// Exit code of a build failing, which Nix gives as 0x60 with a bit set for each way builds failed
+/// (1 timeout, 2 hash mismatch, 4 build failure, 8 check mismatch), so one of 100-111
+fn build_failure_code(err: &anyhow::Error) -> Option<i32> {
+    err.downcast_ref::<NixFailure>()
+        .and_then(|failure| failure.code)
+        .filter(|code| code & !0xf == 0x60 && code & (EXIT_BUILD_FAILED | EXIT_CHECK_MISMATCH) != 0)
+}
+
+/// Whether an error is a build failing (exit codes 100-111), as opposed to Nix or the machine
+/// being broken
+fn is_build_failure(err: &anyhow::Error) -> bool {
+    build_failure_code(err).is_some()
+}
+
+/// Whether a build failure was Nix or the fetcher giving up waiting, rather than the fetch failing
+fn timed_out(err: &anyhow::Error) -> bool {
+    let Some(failure) = err.downcast_ref::<NixFailure>() else {
+        return false;
+    };
+
+    build_failure_code(err).is_some_and(|code| code & EXIT_TIMED_OUT != 0)
+        || failure
+            .log
+            .iter()
+            .any(|line| line.contains("timed out") || line.contains("Timeout was reached"))
+}
+
+/// Hash Nix got instead of the specified one when an error is a fixed-output hash mismatch
+fn got_hash(err: &anyhow::Error) -> Option<String> {
+    err.downcast_ref::<NixFailure>()?
+        .log
+        .iter()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("got:"))
+        .map(|hash| hash.trim().to_string())
+}
+
+/// Hash upstream content had on every failed attempt, or a warning when it changed between them
+fn agreed_hash(hashes: &[String], warnings: &mut Vec<String>) -> Option<String> {
+    let first = hashes.first()?;
+
+    if hashes.iter().all(|hash| hash == first) {
+        Some(first.clone())
+    } else {
+        let mut distinct = hashes.to_vec();
+        distinct.sort();
+        distinct.dedup();
+        warnings.push(format!(
+            "upstream served different content on each attempt: {}",
+            distinct.join(", ")
+        ));
+        None
+    }
+}
+
+/// Lines of stderr kept from each Nix process for inspecting its failure
+const LOG_TAIL: usize = 50;
+
+/// Expression splitting the top-level attrs of Nixpkgs into derivations and package sets
+const SETS_NIX: &str = include_str!("sets.nix");
+
+/// Expression listing the jobs of a Hydra jobset or other `target`
+const JOBS_NIX: &str = include_str!("jobs.nix");
+
+fn keep(tail: &mut VecDeque<String>, line: String) {
+    if tail.len() == LOG_TAIL {
+        tail.pop_front();
+    }
+    tail.push_back(line);
+}
+
+/// Short description of what a Nix command is working on, for prefixing its output
+fn label(cmd: &str, args: &[&str]) -> String {
+    let subject = args
+        .iter()
+        .position(|arg| *arg == "-A")
+        .and_then(|i| args.get(i + 1))
+        .or_else(|| args.iter().find(|arg| arg.starts_with('/')))
+        .map(|subject| subject.rsplit('/').next().unwrap_or(subject));
+
+    match subject {
+        Some(subject) => format!("{} {}", cmd, subject),
+        None => cmd.to_string(),
+    }
+}
+
+/// Runs a command while copying its output to the terminal line by line, keeping stdout in `file`
+fn stream(command: &mut Command, label: &str, mut file: File) -> Result<(ExitStatus, Vec<String>)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Running Nix command")?;
+
+    let stdout = child.stdout.take().expect("Child stdout");
+    let stderr = child.stderr.take().expect("Child stderr");
+
+    let mut tail = VecDeque::new();
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for line in BufReader::new(stdout).split(b'\n').map_while(Result::ok) {
+                println!("[{}] {}", label, String::from_utf8_lossy(&line));
+                if let Err(err) = file.write_all(&line).and_then(|_| file.write_all(b"\n")) {
+                    eprintln!("[{}] Error capturing output: {}", label, err);
+                }
+            }
+        });
+        scope.spawn(|| {
+            let mut log = nixlog::Log::default();
+            for line in BufReader::new(stderr).split(b'\n').map_while(Result::ok) {
+                // Nix reports how its downloads and builds are going, even where nothing is printed
+                systemd::progress();
+                for line in log.text(&String::from_utf8_lossy(&line)) {
+                    eprintln!("[{}] {}", label, line);
+                    keep(&mut tail, line);
+                }
+            }
+        });
+    });
+
+    Ok((
+        child.wait().context("Waiting for Nix command")?,
+        tail.into(),
+    ))
+}
+
+/// Runs a command while passing its stderr through unless quiet, keeping the last lines of it
+fn capture(command: &mut Command) -> Result<(ExitStatus, Vec<String>)> {
+    let mut child = command
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Running Nix command")?;
+
+    let stderr = child.stderr.take().expect("Child stderr");
+
+    let mut tail = VecDeque::new();
+    let mut log = nixlog::Log::default();
+    for line in BufReader::new(stderr).split(b'\n').map_while(Result::ok) {
+        systemd::progress();
+        for line in log.text(&String::from_utf8_lossy(&line)) {
+            if !log::quiet() {
+                eprintln!("{}", line);
+            }
+            keep(&mut tail, line);
+        }
+    }
+
+    Ok((
+        child.wait().context("Waiting for Nix command")?,
+        tail.into(),
+    ))
+}
+
+fn run(opts: &Options, cmd: &str, args: &[&str], path: &[&Path]) -> Result<File> {
+    run_with_stdin(opts, cmd, args, path, None)
+}
+
+fn run_with_stdin(
+    opts: &Options,
+    cmd: &str,
+    args: &[&str],
+    path: &[&Path],
+    stdin: Option<File>,
+) -> Result<File> {
     let nixpkgs_config_dir =
         tempdir().context("Creating temporary directory for Nixpkgs config")?;
     let nixpkgs_config = nixpkgs_config_dir.path().join("nixpkgs-config.nix");
 
     writeln!(
         File::create(nixpkgs_config.clone()).context("Creating Nixpkgs config file")?,
-        "{{ allowAliases = false; }}"
+        "{}",
+        opts.nixpkgs_config()
     )
     .context("Writing Nixpkgs config file")?;
 
     let mut command = Command::new(cmd);
 
     command.env_clear();
+    for var in opts.pass_env() {
+        if let Some(value) = env::var_os(var) {
+            command.env(var, value);
+        }
+    }
+    command.envs(opts.proxy_env());
     if !path.is_empty() {
         command.current_dir(path[0]);
     }
-    command.env("HOME", "/homeless-shelter");
+    if let Some(nix_config) = opts.nix_config() {
+        command.env("NIX_CONFIG", nix_config);
+    }
+    // fetchurl passes NIX_CURL_FLAGS through to curl as an impure variable, which limits builders
+    // too where they run with our environment rather than the daemon's
+    if let Some(bandwidth) = opts.job_bandwidth() {
+        command.env("NIX_CURL_FLAGS", format!("--limit-rate {}", bandwidth));
+    }
+    match &opts.home {
+        Some(home) => command.env("HOME", home),
+        None => command.env("HOME", "/homeless-shelter"),
+    };
+    if let Some(cache_home) = &opts.cache_home {
+        command.env("XDG_CACHE_HOME", cache_home);
+    }
     command.env("NIXPKGS_CONFIG", nixpkgs_config);
-    command.env(
-        "NIX_PATH",
-        path.iter()
-            .map(|p| p.to_str().expect("Path to string"))
-            .collect::<Vec<&str>>()
-            .join(":"),
-    );
+    command.env("NIX_PATH", opts.nix_path(path));
 
-    command.args(["--option", "restrict-eval", "true"]);
+    // Structured logs are turned back into text, without guessing at what Nix meant to print
+    command.args(["--log-format", "internal-json"]);
+    for (name, value) in opts.nix_options() {
+        command.args(["--option", &name, &value]);
+    }
+
+    command.args(&opts.nix_args);
+
+    // Only commands given a Nix path evaluate Nixpkgs
+    if !path.is_empty() {
+        command.envs(opts.eval_env());
+        for (name, value) in opts.eval_options() {
+            command.args(["--option", &name, &value]);
+        }
+    }
 
     command.args(args);
+    if let Some(stdin) = stdin {
+        command.stdin(Stdio::from(stdin));
+    }
 
     let stdout = tempfile().context("Creating temporary file for Nix command")?;
     let mut reader = stdout
         .try_clone()
         .context("Creating reader for temporary file")?;
 
-    let status = command
-        .stdout(Stdio::from(stdout))
-        .status()
-        .context("Running Nix command")?;
+    if opts.print_commands {
+        log::emit(true, format!("+ {}", audit::shell(&command)));
+    }
+
+    let started = Instant::now();
+    let ran = if log::streaming() {
+        stream(&mut command, &label(cmd, args), stdout)
+    } else {
+        capture(command.stdout(Stdio::from(stdout)))
+    };
+    audit::record(
+        &command,
+        ran.as_ref().ok().map(|(status, _)| *status),
+        started.elapsed(),
+    );
+    let (status, log) = ran?;
+    systemd::progress();
 
     reader
         .rewind()
@@ -68,53 +370,183 @@ fn run(cmd: &str, args: &[&str], path: &[&Path]) -> Result<File> {
     if status.success() {
         Ok(reader)
     } else {
-        Err(anyhow!("Nix process failed, see above output"))
+        Err(NixFailure {
+            code: status.code(),
+            log,
+        }
+        .into())
+    }
+}
+
+/// Attr to instantiate, with its package name and version when enumerated by nix-env
+struct Attr {
+    path: String,
+    pname: Option<String>,
+    version: Option<String>,
+}
+
+impl From<String> for Attr {
+    fn from(path: String) -> Attr {
+        Attr {
+            path,
+            pname: None,
+            version: None,
+        }
     }
 }
 
-fn is_fod(drv_path: &Path) -> Result<bool> {
-    let drv = fs::read(drv_path).context(format!("Reading derivation {}", drv_path.display()))?;
+/// Attrs in nix-env --query --json output, keyed by attr path
+fn parse_attrs(value: &serde_json::Value) -> Vec<Attr> {
+    value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(path, info)| Attr {
+            path: path.clone(),
+            pname: info["pname"].as_str().map(String::from),
+            version: info["version"].as_str().map(String::from),
+        })
+        .collect()
+}
+
+fn attrs_in(opts: &Options, nixpkgs: &Path, attr_set: Option<&str>) -> Result<Vec<Attr>> {
+    let mut args = vec!["--query", "--available", "--json", "-f", "."];
+    if let Some(attr_set) = attr_set {
+        args.extend(["-A", attr_set]);
+    }
+
+    let output = run(opts, "nix-env", &args, &[nixpkgs])?;
 
-    Ok(Regex::new(r#"(?-u)^Derive\(\s*\[\s*\(\s*"(?:[^"]+)"\s*,\s*"(?:[^"]+)"\s*,\s*"(?:[^"]+)"\s*,\s*"(?:[^"]+)"\s*\)"#).unwrap().is_match(&drv))
+    Ok(parse_attrs(
+        &serde_json::from_reader(output).context("Parsing nix-env output")?,
+    ))
 }
 
-fn attrs(nixpkgs: &Path) -> Result<Vec<String>> {
+/// Top-level derivations and package sets of Nixpkgs
+fn top_level(opts: &Options, nixpkgs: &Path) -> Result<(Vec<Attr>, Vec<String>)> {
     let output = run(
-        "nix-env",
-        &[
-            "--query",
-            "--available",
-            "--no-name",
-            "--attr-path",
-            "-f",
-            ".",
-        ],
+        opts,
+        "nix-instantiate",
+        &["--eval", "--strict", "--json", "--expr", SETS_NIX],
         &[nixpkgs],
     )?;
 
-    Ok(BufReader::new(output)
-        .lines()
-        .map(|line| line.expect("Read output lines"))
-        .collect())
+    let value = serde_json::from_reader::<_, serde_json::Value>(output)
+        .context("Parsing top-level attrs")?;
+    let sets = value["sets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str().map(String::from))
+        .collect();
+
+    Ok((parse_attrs(&value["derivations"]), sets))
+}
+
+/// Attrs nix-env finds in Nixpkgs, enumerated a package set at a time in parallel rather than in
+/// one slow and memory-hungry query, with sets failing to enumerate left out on their own
+fn attrs_chunked(opts: &Options, nixpkgs: &Path) -> Result<Vec<Attr>> {
+    let (mut attrs, sets) = match top_level(opts, nixpkgs) {
+        Ok(top_level) => top_level,
+        Err(err) => {
+            warn!(
+                "Error splitting up top-level attrs, enumerating them all at once: {:#}",
+                err
+            );
+            return attrs_in(opts, nixpkgs, None);
+        }
+    };
+
+    let in_sets = sets
+        .par_iter()
+        .flat_map(|set| {
+            attrs_in(opts, nixpkgs, Some(set)).unwrap_or_else(|err| {
+                warn!(
+                    "Error enumerating attrs in {}, leaving it out: {:#}",
+                    set, err
+                );
+                vec![]
+            })
+        })
+        .collect::<Vec<Attr>>();
+
+    info!(
+        "Enumerated {} top-level attrs and {} in {} package sets",
+        attrs.len(),
+        in_sets.len(),
+        sets.len()
+    );
+    attrs.extend(in_sets);
+
+    Ok(attrs)
 }
 
-fn instantiate(nixpkgs: &Path, attr: &str, roots_path: &Path) -> Result<PathBuf> {
+/// Attr paths of every job in a `--jobset`, `--file` or `--expr` target, as Hydra would build them
+fn jobs(opts: &Options, nixpkgs: &Path, target: &str) -> Result<Vec<String>> {
     let output = run(
+        opts,
         "nix-instantiate",
         &[
-            ".",
-            "-A",
-            attr,
-            "--add-root",
-            roots_path
-                .join("attrs")
-                .join(attr)
-                .to_str()
-                .expect("Path to string"),
+            "--eval", "--strict", "--json", "--arg", "target", target, "--expr", JOBS_NIX,
         ],
         &[nixpkgs],
     )?;
 
+    serde_json::from_reader(output).context(format!("Parsing jobs of {}", target))
+}
+
+fn attrs(opts: &Options, nixpkgs: &Path) -> Result<Vec<Attr>> {
+    // Requisites of an attr's derivation are its whole build closure, so only it is needed
+    if !opts.closure_of.is_empty() {
+        return Ok(opts.closure_of.iter().cloned().map(Attr::from).collect());
+    }
+    if opts.nixos_config.is_some() {
+        return Ok(vec![Attr::from("system".to_string())]);
+    }
+
+    let mut attrs = match opts.target() {
+        Some(target) => jobs(opts, nixpkgs, &target)?
+            .into_iter()
+            .map(Attr::from)
+            .collect(),
+        None => attrs_chunked(opts, nixpkgs)?,
+    };
+
+    for target in &opts.cross {
+        info!("Generating pkgsCross.{} attrs to check", target);
+
+        attrs.extend(
+            attrs_in(opts, nixpkgs, Some(&format!("pkgsCross.{}", target)))
+                .context(format!("Enumerating attrs in pkgsCross.{}", target))?,
+        );
+    }
+
+    if let Some((shard, shards)) = opts.shard {
+        attrs.retain(|attr| plan::shard_of(&attr.path, shards) == shard);
+        info!(
+            "Checking {} attrs in shard {}/{}",
+            attrs.len(),
+            shard,
+            shards
+        );
+    }
+
+    Ok(attrs)
+}
+
+fn instantiate(opts: &Options, nixpkgs: &Path, attr: &str, roots_path: &Path) -> Result<PathBuf> {
+    let root = roots_path.join("attrs").join(roots::attr_name(attr));
+    let entry = opts.entry();
+    let mut args = entry.iter().map(String::as_str).collect::<Vec<&str>>();
+    args.extend([
+        "-A",
+        attr,
+        "--add-root",
+        root.to_str().expect("Path to string"),
+    ]);
+
+    let output = run(opts, "nix-instantiate", &args, &[nixpkgs])?;
+
     PathBuf::from(
         BufReader::new(output)
             .lines()
@@ -127,218 +559,2525 @@ fn instantiate(nixpkgs: &Path, attr: &str, roots_path: &Path) -> Result<PathBuf>
 }
 
 fn release(attr: &str, roots_path: &Path) -> Result<()> {
-    let root_path = roots_path.join("attrs").join(attr);
+    let root_path = roots_path.join("attrs").join(roots::attr_name(attr));
 
     fs::remove_file(root_path).context("Deleting attribute GC root")
 }
 
-fn requisites(drv_path: &Path) -> Result<Vec<PathBuf>> {
-    let output = run(
-        "nix-store",
-        &[
-            "--query",
-            "--requisites",
-            drv_path.to_str().expect("Path to string"),
-        ],
-        &[],
-    )?;
+/// Store paths in `nix path-info --json` output with their references, given as a list of objects
+/// by older Nix and as an object keyed by path by newer Nix, which can also give bare names
+fn path_infos(value: &serde_json::Value) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    let info = |path: &str, info: &serde_json::Value| {
+        let path = PathBuf::from(path);
+        let store = path.parent().unwrap_or(Path::new("/")).to_owned();
+        let references = info["references"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|reference| reference.as_str())
+            .map(|reference| store.join(reference))
+            .collect();
+        (path, references)
+    };
 
-    Ok(BufReader::new(output)
-        .lines()
-        .map(|line| line.expect("Read output lines").into())
-        .collect())
+    match value {
+        serde_json::Value::Array(infos) => infos
+            .iter()
+            .filter_map(|value| Some(info(value["path"].as_str()?, value)))
+            .collect(),
+        serde_json::Value::Object(infos) => infos
+            .iter()
+            .map(|(path, value)| info(path, value))
+            .collect(),
+        _ => vec![],
+    }
 }
 
-fn realise(drv_path: &Path, roots_path: &Path) -> Result<PathBuf> {
+/// Closure of `drv_path` with the references of each path in it, from a single Nix call
+fn closure(opts: &Options, drv_path: &Path) -> Result<HashMap<PathBuf, Vec<PathBuf>>> {
     let output = run(
-        "nix-store",
+        opts,
+        "nix",
         &[
-            "--realise",
+            "--extra-experimental-features",
+            "nix-command",
+            "path-info",
+            "--json",
+            "--recursive",
             drv_path.to_str().expect("Path to string"),
-            "--add-root",
-            roots_path
-                .join("drvs")
-                .join(drv_path.file_name().expect("Derivation name"))
-                .to_str()
-                .expect("Path to string"),
         ],
         &[],
     )?;
 
-    PathBuf::from(
-        BufReader::new(output)
-            .lines()
-            .next()
-            .ok_or(anyhow!("No derivation in Nix output"))?
-            .context("Reading Nix output")?,
+    Ok(
+        path_infos(&serde_json::from_reader(output).context("Parsing path info")?)
+            .into_iter()
+            .collect(),
     )
-    .read_link()
-    .context("Finding GC root target")
 }
 
-fn check(drv_path: &Path) -> bool {
-    run(
-        "nix-store",
+/// FODs in the closure of `drv_path`, found from one `nix derivation show` rather than by
+/// reading every derivation in it
+fn fixed_outputs(opts: &Options, drv_path: &Path) -> Result<HashSet<PathBuf>> {
+    let output = run(
+        opts,
+        "nix",
         &[
-            "--realise",
-            "--check",
+            "--extra-experimental-features",
+            "nix-command",
+            "derivation",
+            "show",
+            "--recursive",
             drv_path.to_str().expect("Path to string"),
-            "--no-gc-warning",
         ],
         &[],
-    )
-    .is_ok()
+    )?;
+
+    let value =
+        serde_json::from_reader::<_, serde_json::Value>(output).context("Parsing derivations")?;
+    let store = drv_path.parent().unwrap_or(Path::new("/"));
+
+    // Newer Nix keys derivations by name rather than by path
+    Ok(value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, derivation)| {
+            Derivation::from_json(derivation).is_some_and(|derivation| derivation.is_fixed_output())
+        })
+        .map(|(path, _)| store.join(path))
+        .collect())
 }
 
-fn delete(drv_path: &Path, roots_path: &Path) -> Result<()> {
-    let root_path = roots_path
-        .join("drvs")
-        .join(drv_path.file_name().expect("Derivation name"));
+fn requisites(opts: &Options, drv_path: &Path) -> Result<Vec<PathBuf>> {
+    let closure = closure(opts, drv_path)?;
 
-    run(
-        "nix-store",
-        &["--delete", root_path.to_str().expect("Path to string")],
-        &[],
-    )
-    .context(format!("Deleting {}", root_path.display()))?;
+    if let Some(depth) = opts.max_depth {
+        return Ok(requisites_to_depth(&closure, drv_path, depth));
+    }
 
-    Ok(())
+    Ok(closure.into_keys().collect())
 }
 
-fn check_all_fods(nixpkgs: &Path) -> Result<HashMap<(String, PathBuf), bool>> {
-    let cache = env::var("NIXPKGS_FOD_REPORTS_DRV_CACHE").unwrap_or_default();
+/// Derivations at most `depth` inputs away from `drv_path` in its closure
+fn requisites_to_depth(
+    closure: &HashMap<PathBuf, Vec<PathBuf>>,
+    drv_path: &Path,
+    depth: usize,
+) -> Vec<PathBuf> {
+    let mut seen = BTreeSet::from([drv_path.to_owned()]);
+    let mut frontier = vec![drv_path.to_owned()];
 
-    let drvs = Mutex::new(HashMap::<PathBuf, String>::new());
-    let fods = Mutex::new(HashMap::<(String, PathBuf), bool>::new());
+    for _ in 0..depth {
+        if frontier.is_empty() {
+            break;
+        }
 
-    let roots = tempdir().expect("Roots directory");
+        frontier = frontier
+            .iter()
+            .filter_map(|drv| closure.get(drv))
+            .flatten()
+            .filter(|path| path.extension().is_some_and(|extension| extension == "drv"))
+            .filter(|path| seen.insert((*path).clone()))
+            .cloned()
+            .collect();
+    }
 
-    if !cache.is_empty() && Path::new(&cache).try_exists().unwrap_or(false) {
-        drvs.lock().expect("Acquiring derivation mutex").extend(
-            serde_json::from_str::<HashMap<PathBuf, String>>(
-                &fs::read_to_string(&cache).context("Reading derivation cache file")?,
-            )
-            .context("Deserializing derivation cache")?,
-        );
+    seen.into_iter().collect()
+}
+
+fn drv_root(drv_path: &Path, output: &str, roots_path: &Path) -> PathBuf {
+    let mut name = drv_path
+        .file_name()
+        .expect("Derivation name")
+        .to_os_string();
+
+    // Mirrors how nix-store names the GC roots of additional outputs
+    if output != "out" {
+        name.push(format!("-{}", output));
     }
 
-    println!("Generating attrs to check in {}", nixpkgs.display());
+    roots_path.join("drvs").join(name)
+}
 
-    attrs(nixpkgs)?.par_iter().for_each(|attr| {
-        println!("Instantiating {}", attr);
+fn realise(opts: &Options, drv_path: &Path, roots_path: &Path) -> Result<Vec<PathBuf>> {
+    let root_path = drv_root(drv_path, "out", roots_path);
 
-        let reqs = if let Ok(drv) = instantiate(nixpkgs, attr, roots.path()) {
-            if !drvs
-                .lock()
-                .expect("Acquiring derivation mutex")
-                .contains_key(&drv)
-            {
-                println!("Getting requisites for {}", drv.display());
+    let mut args = vec![
+        "--realise",
+        drv_path.to_str().expect("Path to string"),
+        "--add-root",
+        root_path.to_str().expect("Path to string"),
+    ];
+    if !opts.substitute {
+        args.extend(["--option", "substitute", "false"]);
+    }
+    if opts.require_sandbox {
+        args.extend(["--option", "sandbox", "true"]);
+    }
 
-                requisites(&drv).expect("Getting requisite derivations")
-            } else {
-                println!("Ignoring duplicate derivation {}", drv.display());
-                vec![]
-            }
-        } else {
-            eprintln!("Evaluation for {} failed", attr);
+    let output = run(opts, "nix-store", &args, &[])?;
 
-            vec![]
-        };
+    let paths = BufReader::new(output)
+        .lines()
+        .map(|line| {
+            PathBuf::from(line.context("Reading Nix output")?)
+                .read_link()
+                .context("Finding GC root target")
+        })
+        .collect::<Result<Vec<PathBuf>>>()?;
 
-        if let Err(_err) = release(attr, roots.path()) {
-            eprintln!("Failed to release derivation root for {}, ignoring", attr);
-        }
+    if paths.is_empty() {
+        Err(anyhow!("No derivation in Nix output"))
+    } else {
+        Ok(paths)
+    }
+}
 
-        drvs.lock().expect("Acquiring derivation mutex").par_extend(
-            reqs.par_iter()
-                .cloned()
-                .zip(iter::repeatn(attr.clone(), reqs.len())),
-        );
-    });
+fn check(opts: &Options, drv_path: &Path, output: Option<&str>) -> Result<()> {
+    let mut target = drv_path.to_str().expect("Path to string").to_string();
+    if let Some(output) = output {
+        target = format!("{}!{}", target, output);
+    }
 
-    if !cache.is_empty() {
-        fs::write(
-            &cache,
-            serde_json::to_string(&*drvs.lock().expect("Acquiring derivation mutex"))
-                .context("Serializing derivation cache")?,
-        )
-        .context("Writing derivation cache file")?;
+    let mut args = vec!["--realise", "--check", &target, "--no-gc-warning"];
+    if opts.require_sandbox {
+        args.extend(["--option", "sandbox", "true"]);
     }
 
-    drvs.lock()
-        .expect("Acquiring derivation mutex")
-        .par_iter()
-        .for_each(|(drv, attr)| {
-            if !drv.exists() {
-                if let Err(_err) = instantiate(nixpkgs, attr, roots.path()) {
-                    eprintln!(
-                        "Error re-instantiating derivation from {} at {}",
-                        attr,
-                        drv.display()
-                    );
-                }
-            }
+    run(opts, "nix-store", &args, &[]).map(|_| ())
+}
 
-            match is_fod(drv) {
-                Ok(fod) => {
-                    if !fod {
-                        return;
-                    }
-                }
-                Err(_err) => {
-                    eprintln!(
-                        "Error checking whether derivation at {} is a FOD, assuming not",
-                        drv.display()
-                    );
-                    return;
-                }
-            }
+fn verify_path(opts: &Options, out: &Path) -> Result<()> {
+    run(
+        opts,
+        "nix-store",
+        &["--verify-path", out.to_str().expect("Path to string")],
+        &[],
+    )
+    .map(|_| ())
+}
 
-            println!("Realising {}", drv.display());
+/// Realises a derivation in a scratch store given only its inputs, with substitution off, so the
+/// output is fetched from upstream rather than copied from the store in use or a cache
+fn fetch_again(opts: &Options, drv: &Path, output: Option<&str>) -> Result<()> {
+    let drv = drv.to_str().expect("Path to string");
+    let references = run(opts, "nix-store", &["--query", "--references", drv], &[])?;
+    let references = BufReader::new(references)
+        .lines()
+        .collect::<Result<Vec<String>, _>>()
+        .context("Reading Nix output")?;
+    let references = references.iter().map(String::as_str).collect::<Vec<&str>>();
+    // Outputs of the inputs only, the derivation's own being what has to be fetched again
+    let inputs = run(
+        opts,
+        "nix-store",
+        &[
+            &["--query", "--requisites", "--include-outputs"],
+            &references[..],
+        ]
+        .concat(),
+        &[],
+    )?;
+    let mut inputs = BufReader::new(inputs)
+        .lines()
+        .collect::<Result<Vec<String>, _>>()
+        .context("Reading Nix output")?;
+    inputs.push(drv.to_string());
 
-            if let Ok(path) = realise(drv, roots.path()) {
-                fods.lock()
-                    .expect("Acquiring FOD result mutex")
-                    .insert((attr.clone(), drv.to_owned()), check(drv));
+    let scratch = store::Scratch::new()?;
+    let uri = scratch.uri();
+    info!("Copying {} inputs to scratch store {}", inputs.len(), uri);
+    for chunk in inputs.chunks(ARCHIVE_CHUNK) {
+        let paths = chunk.iter().map(String::as_str).collect::<Vec<&str>>();
+        run(
+            opts,
+            "nix",
+            &[
+                &[
+                    "--extra-experimental-features",
+                    "nix-command",
+                    "copy",
+                    "--derivation",
+                    "--no-check-sigs",
+                    "--to",
+                    &uri,
+                ],
+                &paths[..],
+            ]
+            .concat(),
+            &[],
+        )
+        .context("Copying inputs to scratch store")?;
+    }
 
-                if let Err(_err) = release(attr, roots.path()) {
-                    eprintln!("Failed to release derivation root for {}, ignoring", attr);
-                }
+    let mut target = drv.to_string();
+    if let Some(output) = output {
+        target = format!("{}!{}", target, output);
+    }
+    let mut args = vec![
+        "--realise",
+        &target,
+        "--no-gc-warning",
+        "--option",
+        "substitute",
+        "false",
+    ];
+    if opts.require_sandbox {
+        args.extend(["--option", "sandbox", "true"]);
+    }
 
-                if let Err(_err) = delete(drv, roots.path()) {
-                    eprintln!(
-                        "Error removing root and output path from {} at {}",
-                        drv.display(),
-                        path.display(),
-                    );
-                }
-            } else {
-                eprintln!(
-                    "Error realising derivation from {} at {}",
-                    attr,
-                    drv.display(),
+    let scratch_opts = Options {
+        store_access: uri.clone(),
+        ..opts.clone()
+    };
+    run(&scratch_opts, "nix-store", &args, &[]).map(|_| ())
+}
+
+/// Fetches an output once more straight after its check, catching upstreams that generate content
+/// per request even when both fetches disagree with the recorded hash in the same way
+fn double_fetch(opts: &Options, drv: &Path, single: bool, result: &mut FodResult) {
+    let output = (!single).then(|| result.output.clone());
+    let first = result
+        .got_hash
+        .clone()
+        .unwrap_or_else(|| result.expected_hash.clone());
+
+    info!("Fetching {} a second time", result.target());
+    let second = match fetch_again(opts, drv, output.as_deref()) {
+        Ok(()) => result.expected_hash.clone(),
+        Err(err) => match got_hash(&err) {
+            Some(got) => got,
+            None => {
+                warn!(
+                    "Error fetching {} a second time, ignoring: {:#}",
+                    result.target(),
+                    err
                 );
+                return;
             }
-        });
+        },
+    };
 
-    Ok(fods.into_inner().expect("Consuming FOD result mutex"))
+    if first != second {
+        result.status = Status::Nondeterministic;
+        result.got_hash = None;
+        result.warnings.push(format!(
+            "fetched {} and then {} in quick succession",
+            first, second
+        ));
+        result.double_fetched = Some((first, second));
+    }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// Re-fetches an output in place, keeping the store consistent with what upstream serves now
+fn repair(opts: &Options, out: &Path) -> Result<()> {
+    let mut args = vec!["--repair-path", out.to_str().expect("Path to string")];
+    if !opts.substitute {
+        args.extend(["--option", "substitute", "false"]);
+    }
+    if opts.require_sandbox {
+        args.extend(["--option", "sandbox", "true"]);
+    }
 
-    match check_all_fods(Path::new(&args[1])) {
-        Ok(fods) => {
-            for ((attr, drv), reproduced) in fods {
-                if !reproduced {
-                    println!("FOD from {} at {} is not reproducible", attr, drv.display());
-                }
-            }
-        }
-        Err(err) => {
-            eprintln!("Erroring reproducing all FODs: {}", err);
-            process::exit(1);
+    run(opts, "nix-store", &args, &[]).map(|_| ())
+}
+
+/// Retries `attempt` up to `retries` times while it keeps failing, returning its last result and
+/// whether it only succeeded on a retry
+fn confirm<T, E>(
+    opts: &Options,
+    retries: u32,
+    what: &str,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> (Result<T, E>, bool) {
+    let mut result = attempt();
+
+    for retry in 1..=retries {
+        if result.is_ok() {
+            return (result, retry > 1);
         }
+
+        info!("Confirming failure of {} ({}/{})", what, retry, retries);
+        thread::sleep(Duration::from_secs(opts.confirm_delay));
+
+        result = attempt();
+    }
+
+    let flaky = retries > 0 && result.is_ok();
+    (result, flaky)
+}
+
+fn delete(opts: &Options, root_paths: &[PathBuf]) -> Result<()> {
+    let mut args = vec!["--delete"];
+    args.extend(
+        root_paths
+            .iter()
+            .map(|root_path| root_path.to_str().expect("Path to string")),
+    );
+
+    run(opts, "nix-store", &args, &[])?;
+
+    Ok(())
+}
+
+fn nar_hash(opts: &Options, path: &Path) -> Result<String> {
+    let output = run(
+        opts,
+        "nix-store",
+        &["--query", "--hash", path.to_str().expect("Path to string")],
+        &[],
+    )?;
+
+    BufReader::new(output)
+        .lines()
+        .next()
+        .ok_or(anyhow!("No hash in Nix output"))?
+        .context("Reading Nix output")
+}
+
+/// Hash of a directory outside the store as a FOD of it would have, in SRI form
+fn path_hash(opts: &Options, path: &Path, algo: &str) -> Result<String> {
+    let output = run(
+        opts,
+        "nix",
+        &[
+            "--extra-experimental-features",
+            "nix-command",
+            "hash",
+            "path",
+            "--type",
+            algo,
+            path.to_str().expect("Path to string"),
+        ],
+        &[],
+    )?;
+
+    BufReader::new(output)
+        .lines()
+        .next()
+        .ok_or(anyhow!("No hash in Nix output"))?
+        .context("Reading Nix output")
+}
+
+/// Clones the rev a git-based FOD pins and hashes its tree, to tell an archive that changed for
+/// the same rev from a rev that moved
+fn reclone(
+    opts: &Options,
+    derivation: &Derivation,
+    urls: &[String],
+    expected_hash: &str,
+) -> Option<reclone::Verdict> {
+    let source = reclone::source(derivation, urls)?;
+    let expected = Hash::parse(expected_hash, None)?;
+
+    info!("Cloning {} at {} to verify it", source.repo, source.rev);
+    let cloned = tempdir()
+        .context("Creating temporary directory for clone")
+        .and_then(|dir| {
+            if let Some(verdict) = reclone::checkout(&source, dir.path())? {
+                return Ok(Some(verdict));
+            }
+
+            let got = path_hash(opts, dir.path(), &expected.algo)?;
+            if Hash::parse(&got, Some(&expected.algo)).as_ref() == Some(&expected) {
+                Ok(Some(reclone::Verdict::ArchiveChanged))
+            } else if source.archive && reclone::export_attributes(dir.path())? {
+                // The archive was never the same as the tree, so the clone not matching says nothing
+                Ok(None)
+            } else {
+                Ok(Some(reclone::Verdict::RevMoved))
+            }
+        });
+
+    match cloned {
+        Ok(None) => {
+            info!(
+                "Clone of {} cannot be compared, the repository leaves paths out of its archives",
+                source.repo
+            );
+            None
+        }
+        Ok(Some(verdict)) => {
+            info!(
+                "Verified clone of {}: {}",
+                source.repo,
+                verdict.description()
+            );
+            Some(verdict)
+        }
+        Err(err) => {
+            warn!(
+                "Error cloning {} at {} to verify it, ignoring: {:#}",
+                source.repo, source.rev, err
+            );
+            None
+        }
+    }
+}
+
+/// Unpacks the file a flat-hashed output was hashed as, substituting it if needed, and the one
+/// upstream serves now, telling whether the content in them differs, or nothing when the file is
+/// not compressed or an archive
+fn compare_content(
+    opts: &Options,
+    out: &Path,
+    urls: &[String],
+    roots_path: &Path,
+) -> Result<Option<content::Content>> {
+    let root = roots_path.join(out.file_name().expect("Store path name"));
+    let substitute = !store::physical(out).exists();
+    if substitute {
+        info!("Substituting {} to compare its content", out.display());
+        run(
+            opts,
+            "nix-store",
+            &[
+                "--realise",
+                out.to_str().expect("Path to string"),
+                "--add-root",
+                root.to_str().expect("Path to string"),
+                "--option",
+                "substitute",
+                "true",
+            ],
+            &[],
+        )
+        .context("Substituting the file the hash was taken of")?;
+    }
+
+    let dir = tempdir().context("Creating temporary directory for content comparison")?;
+    let expected = dir.path().join("expected");
+    let unpacked = content::unpack(&store::physical(out), &expected);
+    if substitute {
+        if let Err(err) = fs::remove_file(&root) {
+            warn!(
+                "Error removing GC root {}, ignoring: {}",
+                root.display(),
+                err
+            );
+        }
+    }
+    if !unpacked? {
+        return Ok(None);
+    }
+
+    let served = dir.path().join("served");
+    if !urls
+        .iter()
+        .any(|url| http::download(url, &served).unwrap_or(false))
+    {
+        bail!("No URL served the file");
+    }
+    let got = dir.path().join("got");
+    if !content::unpack(&served, &got)? {
+        return Ok(Some(content::Content::Changed));
+    }
+
+    Ok(Some(
+        if path_hash(opts, &expected, "sha256")? != path_hash(opts, &got, "sha256")? {
+            content::Content::Changed
+        } else if content::github_archive(urls) {
+            content::Content::Regenerated
+        } else {
+            content::Content::Recompressed
+        },
+    ))
+}
+
+/// Content comparison for an output with a hash mismatch, for the flat-hashed ones it can be made
+/// for
+fn content(
+    opts: &Options,
+    derivation: &Derivation,
+    result: &FodResult,
+    roots_path: &Path,
+) -> Option<content::Content> {
+    let output = derivation
+        .outputs
+        .iter()
+        .find(|output| output.name == result.output)?;
+    if !content::comparable(output) {
+        return None;
+    }
+
+    compare_content(opts, &result.out, &result.urls, roots_path).unwrap_or_else(|err| {
+        warn!(
+            "Error comparing the content of {}, ignoring: {:#}",
+            result.target(),
+            err
+        );
+        None
+    })
+}
+
+fn nar_size(opts: &Options, path: &Path) -> Result<u64> {
+    let output = run(
+        opts,
+        "nix-store",
+        &["--query", "--size", path.to_str().expect("Path to string")],
+        &[],
+    )?;
+
+    BufReader::new(output)
+        .lines()
+        .next()
+        .ok_or(anyhow!("No size in Nix output"))?
+        .context("Reading Nix output")?
+        .trim()
+        .parse()
+        .context("Parsing NAR size")
+}
+
+/// Evaluates an attribute of an attr's meta to JSON
+fn meta(opts: &Options, nixpkgs: &Path, attr: &str, field: &str) -> Result<serde_json::Value> {
+    let path = format!("{}.meta.{}", attr, field);
+    let entry = opts.entry();
+    let mut args = vec!["--eval", "--strict", "--json"];
+    args.extend(entry.iter().map(String::as_str));
+    args.extend(["-A", &path]);
+
+    let output = run(opts, "nix-instantiate", &args, &[nixpkgs])?;
+
+    serde_json::from_reader(output).context(format!("Parsing meta.{} of {}", field, attr))
+}
+
+/// GitHub handles of an attr's maintainers
+fn maintainers(opts: &Options, nixpkgs: &Path, attr: &str) -> Result<Vec<String>> {
+    Ok(meta(opts, nixpkgs, attr, "maintainers")?
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|maintainer| maintainer["github"].as_str())
+        .map(String::from)
+        .collect())
+}
+
+/// GitHub team slugs of the teams maintaining an attr, which older Nixpkgs does not have
+fn teams(opts: &Options, nixpkgs: &Path, attr: &str) -> Vec<String> {
+    meta(opts, nixpkgs, attr, "teams")
+        .map(|teams| {
+            teams
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|team| team["github"].as_str())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether an attr is maintained by one of the `--maintainer` handles, directly or via a team
+fn maintained(opts: &Options, nixpkgs: &Path, attr: &str) -> Result<bool> {
+    let matches = |handles: Vec<String>| {
+        handles.iter().any(|handle| {
+            opts.maintainers
+                .iter()
+                .any(|wanted| wanted.eq_ignore_ascii_case(handle))
+        })
+    };
+
+    Ok(matches(maintainers(opts, nixpkgs, attr)?) || matches(teams(opts, nixpkgs, attr)))
+}
+
+fn licenses(opts: &Options, nixpkgs: &Path, attr: &str) -> Result<Vec<license::License>> {
+    Ok(license::parse(&meta(opts, nixpkgs, attr, "license")?))
+}
+
+fn swh_lookup(
+    opts: &Options,
+    derivation: &Derivation,
+    drv: &Path,
+    urls: &[String],
+) -> Option<SwhStatus> {
+    swh::lookup(derivation, urls, opts.swh_save)
+        .map_err(|err| {
+            warn!(
+                "Error querying Software Heritage for {}, ignoring: {:#}",
+                drv.display(),
+                err
+            )
+        })
+        .ok()
+}
+
+fn nix_setting(opts: &Options, name: &str) -> Result<String> {
+    let nix_command = ["--extra-experimental-features", "nix-command"];
+
+    if let Ok(output) = run(
+        opts,
+        "nix",
+        &[&nix_command[..], &["config", "show", name]].concat(),
+        &[],
+    ) {
+        return BufReader::new(output)
+            .lines()
+            .next()
+            .ok_or(anyhow!("No {} setting in Nix output", name))?
+            .context("Reading Nix output");
+    }
+
+    // Older Nix only has `nix show-config`, which prints every setting
+    let output = run(
+        opts,
+        "nix",
+        &[&nix_command[..], &["show-config"]].concat(),
+        &[],
+    )?;
+
+    for line in BufReader::new(output).lines() {
+        let line = line.context("Reading Nix output")?;
+
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim() == name {
+                return Ok(value.trim().to_string());
+            }
+        }
+    }
+
+    Err(anyhow!("No {} setting in Nix output", name))
+}
+
+fn check_sandbox(opts: &Options) -> Result<()> {
+    let sandbox = nix_setting(opts, "sandbox").context("Querying sandbox setting")?;
+
+    if sandbox.trim() != "true" {
+        bail!(
+            "Nix has sandbox = {}, refusing to run with --require-sandbox",
+            sandbox.trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Sandbox setting Nix builds with, forced on with --require-sandbox
+fn sandbox_setting(opts: &Options) -> Option<String> {
+    if opts.require_sandbox {
+        return Some("true".to_string());
+    }
+
+    match nix_setting(opts, "sandbox") {
+        Ok(sandbox) => {
+            if sandbox != "true" {
+                warn!(
+                    "Nix has sandbox = {}, so FODs it builds outside the sandbox will be marked unsandboxed",
+                    sandbox
+                );
+            }
+            Some(sandbox)
+        }
+        Err(err) => {
+            warn!(
+                "Error querying sandbox setting, not recording whether checks were sandboxed: {:#}",
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Whether Nix builds a derivation in the sandbox, where relaxed sandboxing lets derivations opt
+/// out with `__noChroot`
+fn sandboxed(setting: Option<&str>, derivation: &Derivation) -> Option<bool> {
+    let no_chroot = derivation
+        .env
+        .get("__noChroot")
+        .is_some_and(|value| value == "1");
+
+    match setting? {
+        "true" => Some(true),
+        "relaxed" => Some(!no_chroot),
+        _ => Some(false),
+    }
+}
+
+fn collect_garbage(opts: &Options, max_freed: u64) -> Result<()> {
+    run(
+        opts,
+        "nix-store",
+        &["--gc", "--max-freed", &max_freed.to_string()],
+        &[],
+    )
+    .context("Collecting garbage")?;
+
+    Ok(())
+}
+
+/// Paths in each `nix-store --export` of the derivation archive, kept short of argument limits
+const ARCHIVE_CHUNK: usize = 1000;
+
+/// Exported closures of the FOD derivations to check, imported again when the garbage collector
+/// removes one before it is checked so that checking never needs to evaluate Nixpkgs again
+#[derive(Default)]
+struct DrvArchive {
+    /// Exports in the order they import in, as later ones can reference paths in earlier ones
+    exports: Vec<File>,
+    restoring: Mutex<()>,
+}
+
+impl DrvArchive {
+    fn export(opts: &Options, queue: &[(PathBuf, String)]) -> DrvArchive {
+        // Derivations from the cache may have been collected since the run that found them
+        let fods = queue
+            .par_iter()
+            .filter(|(drv, _)| {
+                store::physical(drv).exists()
+                    && Derivation::read(drv).is_ok_and(|derivation| derivation.is_fixed_output())
+            })
+            .map(|(drv, _)| drv.to_str().expect("Path to string"))
+            .collect::<Vec<&str>>();
+
+        info!("Archiving {} FOD derivations", fods.len());
+        match DrvArchive::export_closure(opts, &fods) {
+            Ok(exports) => DrvArchive {
+                exports,
+                restoring: Mutex::new(()),
+            },
+            Err(err) => {
+                warn!(
+                    "Error archiving FOD derivations, re-instantiating collected ones: {:#}",
+                    err
+                );
+                DrvArchive::default()
+            }
+        }
+    }
+
+    fn export_closure(opts: &Options, drvs: &[&str]) -> Result<Vec<File>> {
+        // Each query lists references before the paths referring to them, which keeping the
+        // first time a path is seen preserves across queries
+        let mut seen = HashSet::new();
+        let mut closure = Vec::new();
+        for chunk in drvs.chunks(ARCHIVE_CHUNK) {
+            let output = run(
+                opts,
+                "nix-store",
+                &[&["--query", "--requisites"], chunk].concat(),
+                &[],
+            )?;
+            for line in BufReader::new(output).lines() {
+                let path = line.context("Reading Nix output")?;
+                if seen.insert(path.clone()) {
+                    closure.push(path);
+                }
+            }
+        }
+
+        closure
+            .chunks(ARCHIVE_CHUNK)
+            .map(|chunk| {
+                let paths = chunk.iter().map(String::as_str).collect::<Vec<&str>>();
+                run(
+                    opts,
+                    "nix-store",
+                    &[&["--export"], &paths[..]].concat(),
+                    &[],
+                )
+                .context("Exporting derivations")
+            })
+            .collect()
+    }
+
+    /// Imports the archive again for a collected derivation, which restores every other one
+    /// collected so far along with it
+    fn restore(&self, opts: &Options, drv: &Path) {
+        let _restoring = self.restoring.lock().expect("Acquiring archive lock");
+        if self.exports.is_empty() || store::physical(drv).exists() {
+            return;
+        }
+
+        info!(
+            "Restoring collected derivations from the archive for {}",
+            drv.display()
+        );
+        for export in &self.exports {
+            let imported = export
+                .try_clone()
+                .and_then(|mut export| export.rewind().map(|_| export))
+                .context("Reopening archived derivations")
+                .and_then(|export| {
+                    run_with_stdin(opts, "nix-store", &["--import"], &[], Some(export))
+                });
+            if let Err(err) = imported {
+                warn!("Error restoring archived derivations: {:#}", err);
+                return;
+            }
+        }
+    }
+}
+
+/// Tracks consecutive infrastructure errors to give up on runs where Nix itself is broken
+#[derive(Default)]
+struct InfraErrors {
+    consecutive: AtomicUsize,
+    aborted: AtomicBool,
+}
+
+impl InfraErrors {
+    fn record(&self, opts: &Options, infra_error: bool) {
+        if !infra_error {
+            self.consecutive.store(0, Ordering::Relaxed);
+            return;
+        }
+
+        let consecutive = self.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if opts.abort_on_infra_errors
+            && consecutive >= opts.max_infra_errors
+            && !self.aborted.swap(true, Ordering::Relaxed)
+        {
+            error!(
+                "Aborting after {} consecutive infrastructure errors",
+                consecutive
+            );
+        }
+    }
+
+    fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+}
+
+/// Roots of checked outputs waiting to be deleted together, saving a Nix daemon round-trip per FOD
+#[derive(Default)]
+struct Deletions {
+    pending: Mutex<Vec<PathBuf>>,
+}
+
+impl Deletions {
+    fn queue(&self, opts: &Options, root_paths: Vec<PathBuf>) {
+        let batch = {
+            let mut pending = self.pending.lock().expect("Acquiring deletion mutex");
+            pending.extend(root_paths);
+            // Outputs already checked and recorded are the cheapest space to free
+            if pending.len() < opts.delete_batch && !disk::low_on_space(opts) {
+                return;
+            }
+            mem::take(&mut *pending)
+        };
+
+        Deletions::delete(opts, &batch);
+    }
+
+    fn flush(&self, opts: &Options) {
+        let batch = mem::take(&mut *self.pending.lock().expect("Acquiring deletion mutex"));
+
+        if !batch.is_empty() {
+            Deletions::delete(opts, &batch);
+        }
+    }
+
+    fn delete(opts: &Options, root_paths: &[PathBuf]) {
+        // Without deleting, outputs are only unrooted for a later garbage collection to free
+        let deleted = if opts.no_delete {
+            root_paths
+                .iter()
+                .try_for_each(fs::remove_file)
+                .context("Removing roots")
+        } else {
+            delete(opts, root_paths)
+        };
+
+        if let Err(err) = deleted {
+            warn!(
+                "Error removing roots and output paths of {} checked outputs, ignoring: {:#}",
+                root_paths.len(),
+                err
+            );
+        }
+    }
+}
+
+/// Counts of what was left out of a run, by why
+type Skipped = Mutex<BTreeMap<&'static str, usize>>;
+
+fn skip(skipped: &Skipped, reason: &'static str) {
+    *skipped
+        .lock()
+        .expect("Acquiring skipped count mutex")
+        .entry(reason)
+        .or_default() += 1;
+}
+
+/// State shared by every FOD check in a run
+struct RunState<'a> {
+    roots_path: &'a Path,
+    infra: &'a InfraErrors,
+    deletions: &'a Deletions,
+    /// Attrs to retry more, having recently gone between passing and failing
+    quarantine: &'a BTreeSet<String>,
+    skipped: &'a Skipped,
+    sizes: &'a HashMap<PathBuf, u64>,
+    disk: &'a DiskMonitor,
+    archive: &'a DrvArchive,
+    /// Worktree of the revision each derivation enumerated with `--revision` was instantiated in,
+    /// to instantiate it again there
+    trees: &'a HashMap<PathBuf, PathBuf>,
+    /// Derivations only found with aliases allowed, and the options they were instantiated with
+    alias_only: &'a HashSet<PathBuf>,
+    aliased_opts: &'a Options,
+    /// Nix's sandbox setting, when it could be queried
+    sandbox: Option<&'a str>,
+    /// When `--max-duration` runs out, after which no more derivations are checked
+    deadline: Option<Instant>,
+}
+
+impl RunState<'_> {
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Skip reason of the derivations left once `--max-duration` ran out
+const NOT_REACHED: &str = "derivations not reached within --max-duration";
+
+/// Diagnosis of how far upstream could be reached for a failed fetch, which is left out behind a
+/// proxy as it would resolve and connect in our place
+fn reachability(opts: &Options, urls: &[String]) -> Option<diagnose::Reachability> {
+    if opts.proxy.is_some() {
+        return None;
+    }
+
+    let reachability = diagnose::reachability(urls);
+    if let Some(reachability) = reachability {
+        info!("Diagnosed failed fetch: {}", reachability.description());
+    }
+    reachability
+}
+
+fn check_fod(opts: &Options, attr: &str, drv: &Path, ctx: &RunState) -> Vec<FodResult> {
+    let roots_path = ctx.roots_path;
+
+    if ctx.infra.aborted() || systemd::stopping() {
+        skip(ctx.skipped, "derivations not reached before stopping");
+        return vec![];
+    }
+    if ctx.out_of_time() {
+        skip(ctx.skipped, NOT_REACHED);
+        return vec![];
+    }
+
+    if !store::physical(drv).exists() {
+        ctx.archive.restore(opts, drv);
+    }
+    if !store::physical(drv).exists() {
+        let nixpkgs = ctx.trees.get(drv).unwrap_or(&opts.nixpkgs);
+        // Alias attrs only evaluate with aliases allowed
+        let instantiate_opts = if ctx.alias_only.contains(drv) {
+            ctx.aliased_opts
+        } else {
+            opts
+        };
+        if let Err(err) = instantiate(instantiate_opts, nixpkgs, attr, roots_path) {
+            warn!(
+                "Error re-instantiating derivation from {} at {}: {:#}",
+                attr,
+                drv.display(),
+                err
+            );
+        }
+    }
+
+    let derivation = match Derivation::read(drv) {
+        Ok(derivation) => derivation,
+        Err(_err) => {
+            warn!(
+                "Error checking whether derivation at {} is a FOD, assuming not",
+                drv.display()
+            );
+            return vec![];
+        }
+    };
+
+    if !derivation.is_fixed_output() {
+        return vec![];
+    }
+
+    let urls = derivation.urls();
+    let fetcher = fetcher::classify(&derivation, &urls);
+
+    if !opts.fetchers.is_empty() && !opts.fetchers.iter().any(|f| f == fetcher) {
+        skip(ctx.skipped, "FODs filtered out by fetcher");
+        return vec![];
+    }
+
+    if !opts.include_bootstrap && fetcher::is_bootstrap(&derivation, &urls) {
+        skip(ctx.skipped, "bootstrap FODs");
+        return vec![];
+    }
+
+    let urls_alive = if opts.preflight {
+        http::any_alive(&urls)
+    } else {
+        None
+    };
+
+    let ecosystem = ecosystem::classify(&derivation);
+    let lockfile =
+        ecosystem.and_then(|ecosystem| ecosystem::lockfile_context(&derivation, ecosystem));
+
+    let mut results = derivation
+        .outputs
+        .iter()
+        .map(|output| {
+            let out = PathBuf::from(&output.path);
+
+            let cached = opts.binary_cache_check.as_ref().and_then(|cache_url| {
+                cache::narinfo(cache_url, &out).unwrap_or_else(|err| {
+                    warn!(
+                        "Error querying binary cache for {}, ignoring: {:#}",
+                        out.display(),
+                        err
+                    );
+                    None
+                })
+            });
+
+            FodResult {
+                attr: attr.to_string(),
+                drv: drv.to_owned(),
+                output: output.name.clone(),
+                out,
+                status: Status::FetchFailed,
+                fetcher,
+                expected_hash: output.sri_hash(),
+                got_hash: None,
+                double_fetched: None,
+                nar_hash: None,
+                cached,
+                hydra: None,
+                urls: urls.clone(),
+                urls_alive,
+                swh: None,
+                wayback: None,
+                warnings: lint::weak_hash(output).into_iter().collect(),
+                flaky: false,
+                realise_duration: None,
+                check_duration: None,
+                nar_size: None,
+                tags: vec![],
+                known_issue: None,
+                quarantined: false,
+                reachability: None,
+                sandboxed: None,
+                state: None,
+                timed_out: false,
+                revisions: vec![],
+                alias_only: false,
+                reclone: None,
+                content: None,
+                ecosystem,
+                lockfile: lockfile.clone(),
+            }
+        })
+        .collect::<Vec<FodResult>>();
+
+    if opts.lint {
+        results[0]
+            .warnings
+            .extend(lint::unstable_references(&derivation, &urls));
+    }
+
+    if opts.lint_only {
+        for result in &mut results {
+            result.status = Status::Unchecked;
+        }
+        return results;
+    }
+
+    if let Some(max_fod_size) = opts.max_fod_size {
+        for result in &mut results {
+            result.nar_size = result
+                .cached
+                .as_ref()
+                .and_then(|cached| cached.nar_size)
+                .or_else(|| {
+                    schedule::output_size(&result.out, ctx.sizes, Some(opts.size_cache_url()))
+                });
+        }
+
+        let size = results
+            .iter()
+            .filter_map(|result| result.nar_size)
+            .sum::<u64>();
+
+        if size > max_fod_size {
+            info!(
+                "Skipping {} of {} which is over the size limit",
+                drv.display(),
+                report::format_size(size)
+            );
+
+            for result in &mut results {
+                result.status = Status::TooLarge;
+            }
+            return results;
+        }
+    }
+
+    if opts.wayback && urls_alive == Some(false) {
+        let wayback = wayback::first_snapshot(&urls).unwrap_or_else(|err| {
+            warn!(
+                "Error querying Wayback Machine for {}, ignoring: {:#}",
+                drv.display(),
+                err
+            );
+            None
+        });
+
+        for result in &mut results {
+            result.wayback = wayback.clone();
+        }
+    }
+
+    if opts.skip_dead && urls_alive == Some(false) {
+        info!("Skipping {} with dead upstream URLs", drv.display());
+
+        let swh = if opts.swh {
+            swh_lookup(opts, &derivation, drv, &urls)
+        } else {
+            None
+        };
+
+        let reachability = reachability(opts, &urls);
+        for result in &mut results {
+            result.status = Status::DeadUrl;
+            result.swh = swh.clone();
+            result.reachability = reachability;
+        }
+        return results;
+    }
+
+    ctx.disk.wait_for_space(opts, |max_freed| {
+        ctx.deletions.flush(opts);
+        collect_garbage(opts, max_freed)
+    });
+
+    info!("Realising {}", drv.display());
+
+    let sandboxed = sandboxed(ctx.sandbox, &derivation);
+    for result in &mut results {
+        result.sandboxed = sandboxed;
+    }
+
+    let started = Instant::now();
+    let mut got = Vec::new();
+    let retries = if ctx.quarantine.contains(attr) {
+        opts.confirm + opts.quarantine_retries
+    } else {
+        opts.confirm
+    };
+
+    let (realised, flaky) = confirm(
+        opts,
+        retries,
+        &format!("realising {}", drv.display()),
+        || {
+            let realised = realise(opts, drv, roots_path);
+            if let Err(err) = &realised {
+                got.extend(got_hash(err));
+            }
+            realised
+        },
+    );
+    let realise_duration = started.elapsed();
+
+    if let Err(err) = &realised {
+        // Without substitution the first fetch already shows upstream content having changed
+        if !got.is_empty() {
+            for result in &mut results {
+                result.status = Status::NotReproducible;
+                result.got_hash = agreed_hash(&got, &mut result.warnings);
+                result.content = content(opts, &derivation, result, roots_path);
+            }
+        } else if is_build_failure(err) {
+            for result in &mut results {
+                result.timed_out = timed_out(err);
+            }
+        } else {
+            error!(
+                "Infrastructure error realising {}: {:#}",
+                drv.display(),
+                err
+            );
+
+            for result in &mut results {
+                result.status = Status::InfraError;
+            }
+        }
+        ctx.infra.record(opts, !is_build_failure(err));
+    }
+
+    if realised.is_ok() {
+        let single = results.len() == 1;
+
+        for result in &mut results {
+            let output = (!single).then_some(result.output.as_str());
+
+            // A corrupted local copy would make --check report upstream as having changed
+            if opts.verify_store {
+                if let Err(err) = verify_path(opts, &result.out) {
+                    error!(
+                        "Local store corruption in {}: {:#}",
+                        result.out.display(),
+                        err
+                    );
+                    result.realise_duration = Some(realise_duration);
+                    result.status = Status::InfraError;
+                    result
+                        .warnings
+                        .push("local output is corrupted in the store, not checked".to_string());
+                    continue;
+                }
+            }
+
+            let started = Instant::now();
+            let mut got = Vec::new();
+            let (checked, check_flaky) = confirm(
+                opts,
+                retries,
+                &format!("checking {}", result.target()),
+                || {
+                    let checked = check(opts, drv, output);
+                    if let Err(err) = &checked {
+                        got.extend(got_hash(err));
+                    }
+                    checked
+                },
+            );
+            result.realise_duration = Some(realise_duration);
+            result.check_duration = Some(started.elapsed());
+            result.nar_size = nar_size(opts, &result.out).ok();
+
+            result.status = match checked {
+                Ok(()) => Status::Reproducible,
+                Err(err) if is_build_failure(&err) => Status::NotReproducible,
+                Err(err) => {
+                    error!(
+                        "Infrastructure error checking {}: {:#}",
+                        result.target(),
+                        err
+                    );
+                    Status::InfraError
+                }
+            };
+            if result.status == Status::NotReproducible {
+                result.got_hash = agreed_hash(&got, &mut result.warnings);
+                // Before repairing, which replaces the archive the hash was taken of
+                result.content = content(opts, &derivation, result, roots_path);
+
+                if opts.repair {
+                    info!("Repairing {}", result.out.display());
+                    if let Err(err) = repair(opts, &result.out) {
+                        result
+                            .warnings
+                            .push(format!("local output could not be repaired: {:#}", err));
+                    }
+                }
+            }
+            result.flaky = flaky || check_flaky;
+
+            if opts.double_fetch
+                && matches!(
+                    result.status,
+                    Status::Reproducible | Status::NotReproducible
+                )
+            {
+                double_fetch(opts, drv, single, result);
+            }
+
+            ctx.infra.record(opts, result.status == Status::InfraError);
+
+            if result.status != Status::InfraError {
+                result.nar_hash = nar_hash(opts, &result.out).ok();
+            }
+        }
+
+        if let Err(_err) = release(attr, roots_path) {
+            warn!("Failed to release derivation root for {}, ignoring", attr);
+        }
+
+        ctx.deletions.queue(
+            opts,
+            results
+                .iter()
+                .map(|result| drv_root(drv, &result.output, roots_path))
+                .collect(),
+        );
+    } else {
+        error!(
+            "Error realising derivation from {} at {}",
+            attr,
+            drv.display(),
+        );
+    }
+
+    if results
+        .iter()
+        .any(|result| result.status == Status::FetchFailed)
+    {
+        let reachability = reachability(opts, &urls);
+        for result in &mut results {
+            if result.status == Status::FetchFailed {
+                result.reachability = reachability;
+            }
+        }
+    }
+
+    if opts.reclone {
+        for result in results
+            .iter_mut()
+            .filter(|result| result.status == Status::NotReproducible)
+        {
+            result.reclone = reclone(opts, &derivation, &urls, &result.expected_hash);
+        }
+    }
+
+    if results.iter().any(|result| result.status.is_failure()) {
+        let swh = if opts.swh {
+            swh_lookup(opts, &derivation, drv, &urls)
+        } else {
+            None
+        };
+
+        for result in results
+            .iter_mut()
+            .filter(|result| result.status.is_failure())
+        {
+            result.swh = swh.clone();
+
+            if let Some(hydra_url) = &opts.hydra {
+                result.hydra =
+                    hydra::latest_success(hydra_url, drv, &result.out).unwrap_or_else(|err| {
+                        warn!(
+                            "Error querying Hydra for {}, ignoring: {:#}",
+                            drv.display(),
+                            err
+                        );
+                        None
+                    });
+            }
+        }
+    }
+
+    results
+}
+
+/// Instantiates every attr to check, adding their derivations and requisites to `drvs` with the
+/// attr each came from
+fn enumerate(
+    opts: &Options,
+    nixpkgs: &Path,
+    roots_path: &Path,
+    drvs: &Mutex<HashMap<PathBuf, String>>,
+    skipped: &Skipped,
+) -> Result<Vec<AttrTiming>> {
+    let timings = Mutex::new(Vec::<AttrTiming>::new());
+
+    attrs(opts, nixpkgs)?.par_iter().for_each(|entry| {
+        let attr = &entry.path;
+
+        log::group(|| {
+            if systemd::stopping() {
+                return;
+            }
+
+            if !opts.licenses.is_empty() {
+                match licenses(opts, nixpkgs, attr) {
+                    Ok(licenses) if license::allowed(&opts.licenses, &licenses) => {}
+                    Ok(_) => {
+                        info!("Skipping {} for its license", attr);
+                        skip(skipped, "attrs filtered out by license");
+                        return;
+                    }
+                    // Sources that may not be downloaded are excluded rather than risked
+                    Err(_err) => {
+                        warn!("Error evaluating license of {}, skipping", attr);
+                        skip(skipped, "attrs whose license could not be evaluated");
+                        return;
+                    }
+                }
+            }
+
+            if !opts.maintainers.is_empty() {
+                match maintained(opts, nixpkgs, attr) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        skip(skipped, "attrs filtered out by maintainer");
+                        return;
+                    }
+                    Err(_err) => {
+                        warn!("Error evaluating maintainers of {}, skipping", attr);
+                        skip(skipped, "attrs whose maintainers could not be evaluated");
+                        return;
+                    }
+                }
+            }
+
+            info!("Instantiating {}", attr);
+
+            let started = Instant::now();
+            let instantiated = instantiate(opts, nixpkgs, attr, roots_path);
+            let mut timing = AttrTiming {
+                attr: attr.clone(),
+                pname: entry.pname.clone(),
+                version: entry.version.clone(),
+                instantiate: started.elapsed(),
+                requisites: Duration::ZERO,
+                failed: instantiated.is_err(),
+            };
+
+            let reqs = if let Ok(drv) = instantiated {
+                if !drvs
+                    .lock()
+                    .expect("Acquiring derivation mutex")
+                    .contains_key(&drv)
+                {
+                    info!("Getting requisites for {}", drv.display());
+
+                    let started = Instant::now();
+                    let mut reqs = requisites(opts, &drv).expect("Getting requisite derivations");
+                    // The attr's own derivation stays so later attrs sharing it are skipped
+                    match fixed_outputs(opts, &drv) {
+                        Ok(fods) => reqs.retain(|req| *req == drv || fods.contains(req)),
+                        Err(err) => warn!(
+                            "Error finding FODs for {} in one pass, reading each derivation: {:#}",
+                            drv.display(),
+                            err
+                        ),
+                    }
+                    timing.requisites = started.elapsed();
+                    reqs
+                } else {
+                    info!("Ignoring duplicate derivation {}", drv.display());
+                    skip(skipped, "attrs with an already enumerated derivation");
+                    vec![]
+                }
+            } else {
+                error!("Evaluation for {} failed", attr);
+                skip(skipped, "attrs that failed to evaluate");
+
+                vec![]
+            };
+
+            if let Err(_err) = release(attr, roots_path) {
+                warn!("Failed to release derivation root for {}, ignoring", attr);
+            }
+
+            drvs.lock().expect("Acquiring derivation mutex").par_extend(
+                reqs.par_iter()
+                    .cloned()
+                    .zip(iter::repeatn(attr.clone(), reqs.len())),
+            );
+
+            timings
+                .lock()
+                .expect("Acquiring attr timing mutex")
+                .push(timing);
+        })
+    });
+
+    Ok(timings.into_inner().expect("Consuming attr timing mutex"))
+}
+
+fn with_aliases(opts: &Options) -> Options {
+    Options {
+        allow_aliases: true,
+        ..opts.clone()
+    }
+}
+
+/// Enumerates again with aliases allowed, adding the derivations only found through aliases to
+/// `drvs` and returning them
+fn alias_pass(
+    opts: &Options,
+    nixpkgs: &Path,
+    roots_path: &Path,
+    drvs: &Mutex<HashMap<PathBuf, String>>,
+) -> Result<HashSet<PathBuf>> {
+    info!("Generating attrs to check with aliases allowed");
+    systemd::status("Instantiating attrs with aliases allowed");
+
+    let aliased = Mutex::new(HashMap::<PathBuf, String>::new());
+    enumerate(
+        &with_aliases(opts),
+        nixpkgs,
+        roots_path,
+        &aliased,
+        &Skipped::default(),
+    )?;
+
+    let mut drvs = drvs.lock().expect("Acquiring derivation mutex");
+    let mut alias_only = HashSet::new();
+    for (drv, attr) in aliased.into_inner().expect("Consuming derivation mutex") {
+        if let Entry::Vacant(entry) = drvs.entry(drv) {
+            alias_only.insert(entry.key().clone());
+            entry.insert(attr);
+        }
+    }
+
+    info!(
+        "Found {} derivations only reachable through aliases",
+        alias_only.len()
+    );
+    Ok(alias_only)
+}
+
+fn check_all_fods(opts: &Options, quarantine: &BTreeSet<String>) -> Result<Report> {
+    let run_started = Instant::now();
+    let nixpkgs = opts.nixpkgs.as_path();
+    let state_file = |name: &str| {
+        systemd::state_directory()
+            .map(|dir| dir.join(name).display().to_string())
+            .unwrap_or_default()
+    };
+    let cache =
+        env::var("NIXPKGS_FOD_REPORTS_DRV_CACHE").unwrap_or_else(|_| state_file("drv-cache.json"));
+    let size_cache = env::var("NIXPKGS_FOD_REPORTS_SIZE_CACHE")
+        .unwrap_or_else(|_| state_file("size-cache.json"));
+
+    if opts.require_sandbox {
+        check_sandbox(opts)?;
+    }
+
+    let ignored = opts
+        .ignore_file
+        .as_deref()
+        .map(ignore::load)
+        .transpose()?
+        .unwrap_or_default();
+
+    let drvs = Mutex::new(HashMap::<PathBuf, String>::new());
+    let fods = Mutex::new(Vec::<FodResult>::new());
+    // The daemon runs again in the same process, so only what this run downloads counts
+    let downloaded = nixlog::downloaded();
+
+    let roots = Roots::new(opts.roots_dir.as_deref())?;
+
+    if !cache.is_empty() && Path::new(&cache).try_exists().unwrap_or(false) {
+        drvs.lock().expect("Acquiring derivation mutex").extend(
+            serde_json::from_str::<HashMap<PathBuf, String>>(
+                &fs::read_to_string(&cache).context("Reading derivation cache file")?,
+            )
+            .context("Deserializing derivation cache")?,
+        );
+    }
+
+    info!("Generating attrs to check in {}", nixpkgs.display());
+    systemd::status("Instantiating attrs");
+
+    let skipped = Skipped::default();
+    let mut revisions = HashMap::<PathBuf, BTreeSet<String>>::new();
+    // Kept until checking is done, as collected derivations are instantiated again in them
+    let mut worktrees = Vec::new();
+    let mut trees = HashMap::<PathBuf, PathBuf>::new();
+    let timings = if opts.revisions.is_empty() {
+        enumerate(opts, nixpkgs, roots.path(), &drvs, &skipped)?
+    } else {
+        let mut timings = vec![];
+        for revision in &opts.revisions {
+            let worktree = git::Worktree::add(nixpkgs, revision)?;
+            info!("Generating attrs to check in revision {}", revision);
+
+            // Each revision is enumerated on its own so shared derivations are known to be shared,
+            // then checked only once
+            let found = Mutex::new(HashMap::<PathBuf, String>::new());
+            timings.extend(enumerate(
+                opts,
+                &worktree.path,
+                roots.path(),
+                &found,
+                &skipped,
+            )?);
+
+            let mut drvs = drvs.lock().expect("Acquiring derivation mutex");
+            for (drv, attr) in found.into_inner().expect("Consuming derivation mutex") {
+                revisions
+                    .entry(drv.clone())
+                    .or_default()
+                    .insert(revision.clone());
+                if let Entry::Vacant(entry) = drvs.entry(drv) {
+                    trees.insert(entry.key().clone(), worktree.path.clone());
+                    entry.insert(attr);
+                }
+            }
+            worktrees.push(worktree);
+        }
+        timings
+    };
+
+    if !cache.is_empty() {
+        fs::write(
+            &cache,
+            serde_json::to_string(&*drvs.lock().expect("Acquiring derivation mutex"))
+                .context("Serializing derivation cache")?,
+        )
+        .context("Writing derivation cache file")?;
+    }
+
+    // Found after the cache is written, so a later run's cache does not hide what only aliases reach
+    let alias_only = if opts.alias_pass {
+        alias_pass(opts, nixpkgs, roots.path(), &drvs)?
+    } else {
+        HashSet::new()
+    };
+
+    let mut sizes = HashMap::<PathBuf, u64>::new();
+
+    if !size_cache.is_empty() && Path::new(&size_cache).try_exists().unwrap_or(false) {
+        sizes.extend(
+            serde_json::from_str::<HashMap<PathBuf, u64>>(
+                &fs::read_to_string(&size_cache).context("Reading size cache file")?,
+            )
+            .context("Deserializing size cache")?,
+        );
+    }
+
+    let mut queue = drvs
+        .into_inner()
+        .expect("Consuming derivation mutex")
+        .into_iter()
+        .collect::<Vec<(PathBuf, String)>>();
+    schedule::sort(&mut queue);
+
+    if opts.schedule_by_size {
+        info!("Ordering {} derivations by expected size", queue.len());
+
+        schedule::by_size(&mut queue, &sizes, Some(opts.size_cache_url()));
+    }
+
+    if opts.shuffle {
+        let seed = opts.seed.unwrap_or_else(history::now);
+        info!("Shuffling {} derivations with --seed {}", queue.len(), seed);
+
+        schedule::shuffle(&mut queue, seed);
+    }
+
+    if let Some(point) = &opts.start_after {
+        if !schedule::start_after(&mut queue, point) {
+            bail!("Nothing to start after, {} is not in the queue", point);
+        }
+        info!("Starting after {}, {} derivations left", point, queue.len());
+    }
+
+    if let Some(limit) = opts.limit {
+        queue.truncate(limit);
+    }
+
+    // Linting never collects garbage, so derivations stay put without the archive
+    let archive = if opts.lint_only {
+        DrvArchive::default()
+    } else {
+        DrvArchive::export(opts, &queue)
+    };
+
+    let sandbox = sandbox_setting(opts);
+    let aliased_opts = with_aliases(opts);
+    let disk = DiskMonitor::new();
+    let infra = InfraErrors::default();
+    let deletions = Deletions::default();
+    let ctx = RunState {
+        roots_path: roots.path(),
+        infra: &infra,
+        deletions: &deletions,
+        quarantine,
+        skipped: &skipped,
+        sizes: &sizes,
+        disk: &disk,
+        archive: &archive,
+        trees: &trees,
+        alias_only: &alias_only,
+        aliased_opts: &aliased_opts,
+        sandbox: sandbox.as_deref(),
+        deadline: opts
+            .max_duration
+            .map(|max_duration| run_started + Duration::from_secs(max_duration)),
+    };
+
+    let total = queue.len();
+    let done = AtomicUsize::new(0);
+    systemd::status(&format!("Checking {} derivations", total));
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(opts.fetch_jobs())
+        .build()
+        .context("Creating fetch job thread pool")?;
+
+    // Bridging keeps workers picking up derivations roughly in queue order
+    pool.install(|| {
+        queue.into_iter().par_bridge().for_each(|(drv, attr)| {
+            let results = log::group(|| check_fod(opts, &attr, &drv, &ctx));
+
+            fods.lock()
+                .expect("Acquiring FOD result mutex")
+                .extend(results);
+
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            systemd::status(&format!("Checked {} of {} derivations", done, total));
+        })
+    });
+
+    deletions.flush(opts);
+
+    if systemd::stopping() {
+        warn!("Stopping early, only part of the FODs were checked");
+    } else if skipped
+        .lock()
+        .expect("Acquiring skipped count mutex")
+        .contains_key(NOT_REACHED)
+    {
+        warn!("Ran out of --max-duration, only part of the FODs were checked");
+    }
+
+    if infra.aborted() {
+        bail!("Too many consecutive infrastructure errors, Nix or the store appears to be broken");
+    }
+
+    let mut fods = fods.into_inner().expect("Consuming FOD result mutex");
+
+    for result in &mut fods {
+        if let Some(found_in) = revisions.get(&result.drv) {
+            result.revisions = found_in.iter().cloned().collect();
+        }
+        result.alias_only = alias_only.contains(&result.drv);
+    }
+
+    ignore::apply(&ignored, &mut fods);
+
+    match opts.tofu.as_deref().map(Tofu::load) {
+        Some(Ok(mut tofu)) => {
+            for result in &mut fods {
+                if let Some(first) = tofu.observe(result) {
+                    let warning = format!(
+                        "upstream first served {} at {}, but content changed since",
+                        first.hash, first.first_seen
+                    );
+                    warn!("{} for {}", warning, result.target());
+                    result.warnings.push(warning);
+                }
+            }
+
+            if let Err(err) = tofu.save() {
+                warn!("Error saving TOFU database, ignoring: {:#}", err);
+            }
+        }
+        Some(Err(err)) => warn!("Error loading TOFU database, ignoring: {:#}", err),
+        None => {}
+    }
+
+    if let Some(patches_dir) = &opts.patches_dir {
+        match patch::write(nixpkgs, patches_dir, &fods) {
+            Ok(written) => {
+                info!(
+                    "Wrote {} hash-update patches to {}",
+                    written.len(),
+                    patches_dir.display()
+                );
+
+                if let Some(fork) = &opts.open_prs {
+                    for (result, patch) in written {
+                        let maintainers =
+                            maintainers(opts, nixpkgs, &result.attr).unwrap_or_else(|err| {
+                                warn!(
+                                    "Error evaluating maintainers of {}, ignoring: {:#}",
+                                    result.attr, err
+                                );
+                                vec![]
+                            });
+
+                        match pr::open(opts, fork, result, &patch, &maintainers) {
+                            Ok(url) => info!("Opened draft PR {} for {}", url, result.target()),
+                            Err(err) => warn!(
+                                "Error opening PR for {}, ignoring: {:#}",
+                                result.target(),
+                                err
+                            ),
+                        }
+                    }
+                }
+            }
+            Err(err) => warn!("Error writing hash-update patches, ignoring: {:#}", err),
+        }
+    }
+
+    if let Some(mentions) = &opts.mentions {
+        let failing = fods
+            .iter()
+            .filter(|result| result.status.is_failure())
+            .map(|result| result.attr.clone())
+            .collect::<BTreeSet<String>>();
+
+        let by_attr = failing
+            .par_iter()
+            .map(|attr| {
+                let handles = maintainers(opts, nixpkgs, attr).unwrap_or_else(|err| {
+                    warn!(
+                        "Error evaluating maintainers of {}, ignoring: {:#}",
+                        attr, err
+                    );
+                    vec![]
+                });
+                (attr.clone(), handles)
+            })
+            .collect::<BTreeMap<String, Vec<String>>>();
+
+        if let Err(err) = mentions::write(mentions, &by_attr, opts.mentions_per_comment) {
+            warn!("Error writing maintainer mentions, ignoring: {:#}", err);
+        }
+    }
+
+    if !size_cache.is_empty() {
+        sizes.extend(
+            fods.iter()
+                .filter_map(|result| Some((result.out.clone(), result.nar_size?))),
+        );
+
+        fs::write(
+            &size_cache,
+            serde_json::to_string(&sizes).context("Serializing size cache")?,
+        )
+        .context("Writing size cache file")?;
+    }
+
+    Ok(Report {
+        results: fods,
+        attrs: timings,
+        skipped: skipped.into_inner().expect("Consuming skipped count mutex"),
+        downloaded: nixlog::downloaded() - downloaded,
+        revision: git::revision(&opts.nixpkgs),
+        store: Some(opts.store_access.clone()).filter(|store| store != "auto"),
+    })
+}
+
+/// Tells the webhook what changed in the run just appended to the history
+fn send_alerts(opts: &Options, history: &History) {
+    let Some(url) = &opts.notify_webhook else {
+        return;
+    };
+
+    match alerts::notify(url, history, opts.remind_after) {
+        Ok(0) => info!("No FODs changed state, not notifying"),
+        Ok(sent) => info!("Notified webhook of {} FOD state changes", sent),
+        Err(err) => warn!("Error notifying webhook, ignoring: {:#}", err),
+    }
+}
+
+/// Uploads the run to the aggregation server, which a failed upload does not fail the run for
+fn submit_report(opts: &Options, report: &Report, started: u64, revision: Option<&str>) {
+    let Some(url) = &opts.submit else {
+        return;
+    };
+
+    let token = match fs::read_to_string(opts.submit_token_file.as_ref().expect("Submit token")) {
+        Ok(token) => token.trim().to_string(),
+        Err(err) => {
+            warn!("Error reading submit token, not submitting: {:#}", err);
+            return;
+        }
+    };
+
+    let payload = submit::payload(report, started, revision, opts.shard);
+    match submit::submit(url, &token, &payload) {
+        Ok(Some(shown)) => info!("Submitted results to {}, shown at {}", url, shown),
+        Ok(None) => info!("Submitted results to {}", url),
+        Err(err) => warn!("Error submitting results, ignoring: {:#}", err),
+    }
+}
+
+/// Checks and reports on every FOD, returning the run to record in the history
+fn check_and_report(opts: &Options, history: Option<&History>) -> Result<Run> {
+    let started = history::now();
+    let revision = git::revision(&opts.nixpkgs);
+
+    let quarantine = history
+        .map(|history| quarantine::quarantined(history, opts.quarantine_stable_runs))
+        .unwrap_or_default();
+    if !quarantine.is_empty() {
+        info!(
+            "Retrying {} quarantined attrs more, until they are stable",
+            quarantine.len()
+        );
+    }
+
+    let mut report = check_all_fods(opts, &quarantine)?;
+
+    for result in &mut report.results {
+        result.quarantined = quarantine.contains(&result.attr);
+        if let Some(history) = history {
+            result.tags = history.tags(&result.attr);
+        }
+    }
+
+    state::apply(history, &mut report.results, opts.broken_after);
+
+    if let Some(previous) = history.and_then(History::latest) {
+        for result in &mut report.results {
+            if let Some(got) = previous.retagged(result) {
+                let warning = format!(
+                    "upstream served {} in the previous run, but different content now",
+                    got
+                );
+                warn!("{} for {}", warning, result.target());
+                result.warnings.push(warning);
+            }
+        }
+    }
+
+    report::print(opts, &mut report);
+    submit_report(opts, &report, started, revision.as_deref());
+
+    Ok(Run::new(
+        started,
+        revision,
+        report.store.clone(),
+        &report.results,
+    ))
+}
+
+/// Fetches `reference` and checks it out, returning whether it moved past the last checked commit
+fn advance(opts: &Options, reference: &str, history: &History) -> Result<bool> {
+    let nixpkgs = opts.nixpkgs.as_path();
+
+    git::git(nixpkgs, &["fetch", "--quiet", "origin", reference])
+        .context(format!("Fetching {}", reference))?;
+    let fetched = git::git(nixpkgs, &["rev-parse", "FETCH_HEAD"])?;
+
+    if history.latest().and_then(|run| run.revision.as_ref()) == Some(&fetched) {
+        return Ok(false);
+    }
+
+    git::git(nixpkgs, &["checkout", "--quiet", "--detach", &fetched])
+        .context(format!("Checking out {}", fetched))?;
+
+    Ok(true)
+}
+
+fn daemon(opts: &Options) -> Result<()> {
+    let history_path = opts.history.as_ref().expect("Daemon history path");
+    let history = Arc::new(RwLock::new(History::load(history_path)?));
+
+    // The server is left running detached, as nothing stops it waiting for connections
+    if let Some(listen) = opts.listen.clone() {
+        let history = Arc::clone(&history);
+        thread::spawn(move || {
+            if let Err(err) = server::serve(&listen, |request| api::handle(&history, request)) {
+                error!("Error serving the HTTP API: {:#}", err);
+            }
+        });
+    }
+
+    systemd::notify("READY=1");
+
+    while !systemd::stopping() {
+        let started = Instant::now();
+
+        let due = match &opts.follow {
+            Some(reference) => {
+                let history = history.read().expect("Acquiring history lock");
+                advance(opts, reference, &history).unwrap_or_else(|err| {
+                    error!("Error following {}: {:#}", reference, err);
+                    false
+                })
+            }
+            None => true,
+        };
+
+        if due {
+            // Only readers wait on the history until the run is recorded
+            let checked = {
+                let history = history.read().expect("Acquiring history lock");
+                check_and_report(opts, Some(&history))
+            };
+            let recorded = checked.and_then(|run| {
+                let mut history = history.write().expect("Acquiring history lock");
+                history.append(run).context("Recording run in history")?;
+                send_alerts(opts, &history);
+                Ok(())
+            });
+
+            if let Err(err) = recorded {
+                error!("Error reproducing all FODs: {:#}", err);
+            }
+        } else {
+            info!("Nixpkgs has not advanced since the last run, waiting");
+        }
+
+        systemd::status("Waiting for the next run");
+
+        let interval = Duration::from_secs(opts.interval);
+        while !systemd::stopping() && started.elapsed() < interval {
+            systemd::progress();
+            thread::sleep(Duration::from_secs(1).min(interval.saturating_sub(started.elapsed())));
+        }
+    }
+
+    systemd::notify("STOPPING=1");
+
+    Ok(())
+}
+
+/// Prints CI jobs checking the attrs in shards, counting each shard's attrs to help pick --shards
+fn plan(opts: &Options) -> Result<()> {
+    let nixpkgs = opts.nixpkgs.as_path();
+
+    info!("Generating attrs to plan in {}", nixpkgs.display());
+    let mut counts = vec![0; opts.shards];
+    for attr in attrs(opts, nixpkgs)? {
+        counts[plan::shard_of(&attr.path, opts.shards) - 1] += 1;
+    }
+
+    print!("{}", plan::emit(&opts.emit, &opts.args, &counts));
+
+    Ok(())
+}
+
+/// Whether a FOD passes the --fetcher and bootstrap filters, for commands not realising anything
+fn listed(opts: &Options, derivation: &Derivation) -> bool {
+    let urls = derivation.urls();
+
+    (opts.fetchers.is_empty()
+        || opts
+            .fetchers
+            .iter()
+            .any(|f| f == fetcher::classify(derivation, &urls)))
+        && (opts.include_bootstrap || !fetcher::is_bootstrap(derivation, &urls))
+}
+
+/// Writes per-run aggregates from the history for charting trends
+fn export_history(opts: &Options) -> Result<()> {
+    let history = History::load(opts.history.as_ref().expect("History"))?;
+
+    let rendered = timeseries::render(&history, &opts.format);
+    match &opts.output {
+        Some(output) => fs::write(output, rendered)
+            .context(format!("Writing timeseries to {}", output.display()))?,
+        None => print!("{}", rendered),
+    }
+
+    Ok(())
+}
+
+/// Writes every distinct upstream URL of the FODs to check, without fetching any of them
+fn list_urls(opts: &Options) -> Result<()> {
+    let nixpkgs = opts.nixpkgs.as_path();
+    let roots = Roots::new(opts.roots_dir.as_deref())?;
+    let drvs = Mutex::new(HashMap::<PathBuf, String>::new());
+
+    info!("Generating attrs to list URLs of in {}", nixpkgs.display());
+    enumerate(opts, nixpkgs, roots.path(), &drvs, &Skipped::default())?;
+
+    let inventory = Mutex::new(inventory::Inventory::new());
+    drvs.into_inner()
+        .expect("Consuming derivation mutex")
+        .par_iter()
+        .for_each(|(drv, attr)| {
+            let derivation = match Derivation::read(drv) {
+                Ok(derivation) if derivation.is_fixed_output() => derivation,
+                _ => return,
+            };
+
+            if !listed(opts, &derivation) {
+                return;
+            }
+
+            inventory::add(
+                &mut inventory.lock().expect("Acquiring URL inventory mutex"),
+                &derivation.urls(),
+                attr,
+            );
+        });
+
+    let inventory = inventory
+        .into_inner()
+        .expect("Consuming URL inventory mutex");
+    info!("Found {} distinct URLs", inventory.len());
+
+    let rendered = inventory::render(&inventory, &opts.format);
+    match &opts.output {
+        Some(output) => fs::write(output, rendered)
+            .context(format!("Writing URL inventory to {}", output.display()))?,
+        None => print!("{}", rendered),
+    }
+
+    Ok(())
+}
+
+/// Hash a Nixpkgs checkout records for a FOD output in the closure of an attr, by derivation name
+fn recorded_hash(
+    opts: &Options,
+    nixpkgs: &Path,
+    roots_path: &Path,
+    attr: &str,
+    name: &str,
+    output: &str,
+) -> Result<Option<String>> {
+    let drv = instantiate(opts, nixpkgs, attr, roots_path)?;
+    let reqs = requisites(opts, &drv);
+
+    if let Err(_err) = release(attr, roots_path) {
+        warn!("Failed to release derivation root for {}, ignoring", attr);
+    }
+
+    for req in reqs? {
+        let Ok(derivation) = Derivation::read(&req) else {
+            continue;
+        };
+        if !derivation.is_fixed_output() || compare::name(&req) != name {
+            continue;
+        }
+
+        if let Some(output) = derivation.outputs.iter().find(|o| o.name == output) {
+            return Ok(Some(output.sri_hash()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Bisects from `since` to `head` for the commit that stopped recording `upstream`, returning it
+fn first_changed(
+    opts: &Options,
+    worktree: &Path,
+    roots_path: &Path,
+    result: &FodResult,
+    upstream: &str,
+    since: &str,
+    head: &str,
+) -> Result<Option<String>> {
+    let name = compare::name(&result.drv);
+    let mut output = git::git(worktree, &["bisect", "start", head, since])?;
+
+    let first_bad = loop {
+        if let Some(commit) = output
+            .lines()
+            .find_map(|line| line.strip_suffix(" is the first bad commit"))
+        {
+            break Some(commit.to_string());
+        }
+
+        // Revisions failing to evaluate or without the FOD say nothing either way
+        let verdict = match recorded_hash(
+            opts,
+            worktree,
+            roots_path,
+            &result.attr,
+            &name,
+            &result.output,
+        ) {
+            Ok(Some(hash)) if hash == upstream => "good",
+            Ok(Some(_)) => "bad",
+            _ => "skip",
+        };
+        info!(
+            "Marking {} as {} for {}",
+            git::revision(worktree).unwrap_or_default(),
+            verdict,
+            name
+        );
+
+        match git::git(worktree, &["bisect", verdict]) {
+            Ok(next) => output = next,
+            Err(err) => {
+                warn!("Bisecting {} stopped: {:#}", name, err);
+                break None;
+            }
+        }
+    };
+
+    if let Err(err) = git::git(worktree, &["bisect", "reset"]) {
+        warn!("Error resetting bisect, ignoring: {:#}", err);
+    }
+
+    Ok(first_bad)
+}
+
+fn bisect(opts: &Options) -> Result<()> {
+    let nixpkgs = opts.nixpkgs.as_path();
+    let attr = opts.attr.as_ref().expect("Attr to bisect");
+
+    let since = match &opts.since {
+        Some(since) => since.clone(),
+        None => opts
+            .history
+            .as_deref()
+            .map(History::load)
+            .transpose()?
+            .and_then(|history| {
+                history
+                    .runs
+                    .iter()
+                    .rev()
+                    .find(|run| {
+                        run.records.iter().any(|record| {
+                            record.attr == *attr && record.status == Status::Reproducible
+                        })
+                    })
+                    .and_then(|run| run.revision.clone())
+            })
+            .ok_or(anyhow!(
+                "No run in the history had {} reproducible, so bisect needs --since",
+                attr
+            ))?,
+    };
+    let head = git::revision(nixpkgs).ok_or(anyhow!("Nixpkgs is not a git checkout"))?;
+
+    info!("Checking {} against upstream", attr);
+    let check = Options {
+        closure_of: vec![attr.clone()],
+        revisions: vec![],
+        ..opts.clone()
+    };
+    let report = check_all_fods(&check, &BTreeSet::new())?;
+
+    let mismatches = report
+        .results
+        .iter()
+        .filter(|result| result.attr == *attr && result.status == Status::NotReproducible)
+        .filter_map(|result| Some((result, result.got_hash.as_deref()?)))
+        .collect::<Vec<(&FodResult, &str)>>();
+    if mismatches.is_empty() {
+        info!("Every FOD of {} matches upstream, nothing to bisect", attr);
+        return Ok(());
+    }
+
+    let roots = Roots::new(opts.roots_dir.as_deref())?;
+    let worktree = git::Worktree::add(nixpkgs, &since)?;
+
+    for (result, got) in mismatches {
+        let name = compare::name(&result.drv);
+        let upstream = Hash::parse(got, None)
+            .map(|hash| hash.encode(Encoding::Sri))
+            .unwrap_or(got.to_string());
+
+        match recorded_hash(
+            opts,
+            &worktree.path,
+            roots.path(),
+            attr,
+            &name,
+            &result.output,
+        ) {
+            Ok(Some(hash)) if hash == upstream => {}
+            Ok(Some(hash)) => {
+                println!(
+                    "{}: upstream changed, {} already recorded {} but upstream now serves {}",
+                    result.target(),
+                    since,
+                    hash,
+                    upstream
+                );
+                continue;
+            }
+            Ok(None) => {
+                warn!("{} has no FOD named {} at {}, skipping", attr, name, since);
+                continue;
+            }
+            Err(err) => {
+                warn!(
+                    "Error evaluating {} at {}, skipping: {:#}",
+                    attr, since, err
+                );
+                continue;
+            }
+        }
+
+        info!("Bisecting {} from {} to {}", name, since, head);
+        match first_changed(
+            opts,
+            &worktree.path,
+            roots.path(),
+            result,
+            &upstream,
+            &since,
+            &head,
+        )? {
+            Some(commit) => {
+                let last = git::git(&worktree.path, &["rev-parse", &format!("{}^", commit)])?;
+                println!(
+                    "{}: Nixpkgs changed, {} is the last revision recording upstream's {}, {} changed it",
+                    result.target(),
+                    last,
+                    upstream,
+                    commit
+                );
+            }
+            None => println!(
+                "{}: could not tell which commit changed the hash from {}",
+                result.target(),
+                upstream
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn compare(opts: &Options) -> Result<()> {
+    let nixpkgs = opts.nixpkgs.as_path();
+    let (old, new) = opts.compare.as_ref().expect("Revisions to compare");
+    let roots = Roots::new(opts.roots_dir.as_deref())?;
+
+    let mut surfaces = vec![];
+    for revision in [old, new] {
+        let worktree = git::Worktree::add(nixpkgs, revision)?;
+        let drvs = Mutex::new(HashMap::<PathBuf, String>::new());
+
+        info!("Generating attrs to compare in revision {}", revision);
+        enumerate(
+            opts,
+            &worktree.path,
+            roots.path(),
+            &drvs,
+            &Skipped::default(),
+        )?;
+
+        let surface = Mutex::new(compare::Surface::new());
+        drvs.into_inner()
+            .expect("Consuming derivation mutex")
+            .par_iter()
+            .for_each(|(drv, attr)| match Derivation::read(drv) {
+                Ok(derivation) if derivation.is_fixed_output() && listed(opts, &derivation) => {
+                    compare::add(
+                        &mut surface.lock().expect("Acquiring FOD surface mutex"),
+                        drv,
+                        &derivation,
+                        attr,
+                    )
+                }
+                _ => {}
+            });
+
+        surfaces.push(surface.into_inner().expect("Consuming FOD surface mutex"));
+    }
+
+    let changes = compare::diff(&surfaces[0], &surfaces[1]);
+    info!(
+        "Found {} changed FOD outputs from {} to {}",
+        changes.len(),
+        old,
+        new
+    );
+
+    let rendered = compare::render(&changes, &opts.format);
+    match &opts.output {
+        Some(output) => fs::write(output, rendered)
+            .context(format!("Writing comparison to {}", output.display()))?,
+        None => print!("{}", rendered),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let mut opts = match Options::parse(env::args().skip(1)) {
+        Ok(Some(opts)) => opts,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    systemd::start();
+
+    // Our own requests with curl and git go through the proxy too
+    for (var, value) in opts.proxy_env() {
+        env::set_var(var, value);
+    }
+
+    if let Some(audit_log) = &opts.audit_log {
+        if let Err(err) = audit::open(audit_log) {
+            eprintln!("{:#}", err);
+            process::exit(1);
+        }
+    }
+
+    log::set_color(&opts.color);
+    log::set_verbosity(if opts.quiet {
+        log::QUIET
+    } else {
+        log::NORMAL + opts.verbose
+    });
+
+    // Commands only reading the history or reports never run Nix
+    if !["report serve", "tag", "untag", "history export"].contains(&opts.command.as_str()) {
+        match store::resolve(&opts) {
+            Ok(store) => {
+                info!("Using Nix store {}", store);
+                opts.store_access = store;
+            }
+            Err(err) => {
+                eprintln!("{:#}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Fetchers and Nix's own caches need a writable HOME, which is kept apart from ours and
+    // starts out empty each run unless one is given
+    let home = match opts.home {
+        Some(_) => None,
+        None => match tempdir().context("Creating temporary home directory") {
+            Ok(home) => {
+                opts.home = Some(home.path().to_owned());
+                Some(home)
+            }
+            Err(err) => {
+                eprintln!("{:#}", err);
+                process::exit(1);
+            }
+        },
+    };
+
+    let result = if opts.command == "report serve" {
+        let dir = opts.dir.as_ref().expect("Report directory");
+        server::serve(
+            opts.listen.as_deref().unwrap_or(files::DEFAULT_LISTEN),
+            |request| files::handle(dir, request),
+        )
+    } else if opts.command == "daemon" {
+        daemon(&opts)
+    } else if opts.command == "plan" {
+        plan(&opts)
+    } else if opts.command == "list-urls" {
+        list_urls(&opts)
+    } else if opts.command == "compare" {
+        compare(&opts)
+    } else if opts.command == "bisect" {
+        bisect(&opts)
+    } else if opts.command == "history export" {
+        export_history(&opts)
+    } else if opts.command == "tag" || opts.command == "untag" {
+        let (attr, tag) = opts.tag.as_ref().expect("Attr and tag");
+        opts.history
+            .as_ref()
+            .map(|path| History::load(path))
+            .expect("History")
+            .and_then(|mut history| history.tag(attr, tag, opts.command == "untag"))
+    } else {
+        opts.history
+            .as_ref()
+            .map(|path| History::load(path))
+            .transpose()
+            .and_then(|history| {
+                let run = check_and_report(&opts, history.as_ref())?;
+                if let Some(mut history) = history {
+                    history.append(run).context("Recording run in history")?;
+                    send_alerts(&opts, &history);
+                }
+                Ok(())
+            })
+    };
+
+    if let Err(err) = result {
+        eprintln!("Erroring reproducing all FODs: {}", err);
+        drop(home);
+        process::exit(1);
     }
 }
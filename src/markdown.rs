@@ -0,0 +1,167 @@
+use crate::report::{self, FodResult, Report, Status};
+
+/// Backticks would end an inline code span early
+fn code(text: &str) -> String {
+    format!("`{}`", text.replace('`', "'"))
+}
+
+fn table(results: &[&FodResult]) -> String {
+    let mut table =
+        "| Attr | Derivation | Fetcher | Tags | Expected | Got | NAR hash | Status |\n| --- | --- | --- | --- | --- | --- | --- | --- |\n"
+            .to_string();
+
+    for (result, also) in report::collapse(results) {
+        let mut attr = code(&result.attr);
+        if let Some(lockfile) = &result.lockfile {
+            attr.push_str(&format!(
+                "<br>vendored with {}",
+                lockfile.replace('|', "\\|")
+            ));
+        }
+        if !also.is_empty() {
+            attr.push_str(&format!(
+                "<br>also affects {}",
+                also.iter()
+                    .map(|other| format!("{} at {}", code(&other.attr), code(&other.target())))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
+
+        table.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} |\n",
+            attr,
+            code(&result.target()),
+            result.fetcher,
+            result.tags().join(", "),
+            code(&result.expected_hash),
+            result.observed_hash().map(code).unwrap_or_default(),
+            result.nar_hash.as_deref().map(code).unwrap_or_default(),
+            result.status.description()
+        ));
+    }
+
+    table
+}
+
+/// Markdown report with a collapsible section for each package set
+pub fn render(report: &Report) -> String {
+    let results = &report.results;
+    let mut markdown = format!(
+        "# FOD reproducibility report\n\n{}\n",
+        report::summary(results)
+    );
+    if let Some(states) = report::states(results) {
+        markdown.push_str(&format!("\nStates: {}\n", states));
+    }
+    if let Some(ecosystems) = report::ecosystems(results) {
+        markdown.push_str(&format!("\nEcosystems: {}\n", ecosystems));
+    }
+    if let Some(bandwidth) = report::bandwidth(report) {
+        markdown.push_str(&format!("\nBandwidth: {}\n", bandwidth));
+    }
+
+    let reported = report::reported(results);
+    for (class, members) in report::by_reason(&reported) {
+        markdown.push_str(&format!("\n## {} ({})\n\n", class, members.len()));
+        markdown.push_str(&table(&members));
+    }
+    let eval_failures = report::eval_failures(report);
+    if !eval_failures.is_empty() {
+        markdown.push_str(&format!("\n## Eval failure ({})\n\n", eval_failures.len()));
+        for attr in eval_failures {
+            markdown.push_str(&format!("- {}\n", code(attr)));
+        }
+    }
+    markdown.push_str("\n## By package set\n");
+
+    for (group, members) in report::groups(results) {
+        let unreproducible = members
+            .iter()
+            .copied()
+            .filter(|result| {
+                result.status != Status::Reproducible
+                    && result.known_issue.is_none()
+                    && !result.quarantined
+            })
+            .collect::<Vec<&FodResult>>();
+        let failing = unreproducible
+            .iter()
+            .filter(|result| result.status.is_failure())
+            .count();
+
+        markdown.push_str(&format!(
+            "\n<details><summary><b>{}</b>: {} of {} FOD outputs failing</summary>\n\n",
+            group,
+            failing,
+            members.len()
+        ));
+        if unreproducible.is_empty() {
+            markdown.push_str("All reproducible.\n");
+        } else {
+            markdown.push_str(&table(&unreproducible));
+        }
+        markdown.push_str("\n</details>\n");
+    }
+
+    let coverage = report::coverage(report);
+    if !coverage.is_empty() {
+        markdown.push_str(&format!(
+            "\n## Coverage\n\n{} of {} FOD outputs checked, left out:\n\n",
+            report::checked(results),
+            results.len()
+        ));
+        for (reason, count) in coverage {
+            markdown.push_str(&format!("- {}: {}\n", reason, count));
+        }
+    }
+
+    let by_revision = report::by_revision(results);
+    if !by_revision.is_empty() {
+        markdown.push_str("\n## Per revision\n\n");
+        for (revision, results) in by_revision {
+            markdown.push_str(&format!(
+                "- {}: {}\n",
+                code(revision),
+                report::summary(results)
+            ));
+        }
+    }
+
+    let alias_only = report::alias_only(results);
+    if !alias_only.is_empty() {
+        markdown.push_str(&format!(
+            "\n## Only reachable through aliases ({})\n\n",
+            alias_only.len()
+        ));
+        markdown.push_str(&table(&alias_only));
+    }
+
+    let quarantined = report::quarantined(results);
+    if !quarantined.is_empty() {
+        markdown.push_str("\n## Quarantined as flaky\n\n");
+        markdown.push_str(&table(&quarantined));
+    }
+
+    let known = report::known_issues(results);
+    if !known.is_empty() {
+        markdown.push_str(
+            "\n## Known issues\n\n| Attr | Derivation | Status | Reason |\n| --- | --- | --- | --- |\n",
+        );
+        for result in known {
+            markdown.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                code(&result.attr),
+                code(&result.target()),
+                result.status.description(),
+                result
+                    .known_issue
+                    .as_deref()
+                    .unwrap_or_default()
+                    .replace('|', "\\|")
+            ));
+        }
+    }
+
+    markdown
+}
@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// GitHub stops notifying people mentioned past this many in a single comment
+pub const DEFAULT_PER_COMMENT: usize = 50;
+
+fn attr_list(attrs: &[&str]) -> String {
+    attrs
+        .iter()
+        .map(|attr| format!("`{}`", attr))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Markdown comments mentioning the maintainers of failing attrs, with at most `per_comment`
+/// mentions in each
+fn markdown(maintainers: &BTreeMap<String, Vec<String>>, per_comment: usize) -> String {
+    let mut by_maintainer = BTreeMap::<&str, Vec<&str>>::new();
+    let mut unmaintained = Vec::new();
+
+    for (attr, handles) in maintainers {
+        if handles.is_empty() {
+            unmaintained.push(attr.as_str());
+        }
+        for handle in handles {
+            let attrs = by_maintainer.entry(handle).or_default();
+            if !attrs.contains(&attr.as_str()) {
+                attrs.push(attr);
+            }
+        }
+    }
+
+    let by_maintainer = by_maintainer
+        .into_iter()
+        .collect::<Vec<(&str, Vec<&str>)>>();
+    let chunks = by_maintainer
+        .chunks(per_comment.max(1))
+        .collect::<Vec<&[(&str, Vec<&str>)]>>();
+    let count = chunks.len().max(1);
+
+    let mut comments = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let mut comment = format!("### Failing FODs by maintainer ({}/{})\n\n", i + 1, count);
+        for (handle, attrs) in chunk.iter() {
+            comment.push_str(&format!("- @{}: {}\n", handle, attr_list(attrs)));
+        }
+        comments.push(comment);
+    }
+
+    if !unmaintained.is_empty() {
+        if comments.is_empty() {
+            comments.push("### Failing FODs by maintainer (1/1)\n\n".to_string());
+        }
+        let last = comments.last_mut().expect("Last comment");
+        last.push_str(&format!("- No maintainers: {}\n", attr_list(&unmaintained)));
+    }
+
+    comments.join("\n<!-- Paste each section as a separate comment -->\n\n")
+}
+
+pub fn write(
+    path: &Path,
+    maintainers: &BTreeMap<String, Vec<String>>,
+    per_comment: usize,
+) -> Result<()> {
+    fs::write(path, markdown(maintainers, per_comment))
+        .context(format!("Writing mention list {}", path.display()))
+}
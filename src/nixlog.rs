@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_json::Value;
+
+use crate::report::format_size;
+
+/// Prefix of each structured line Nix writes to stderr with `--log-format internal-json`
+const PREFIX: &str = "@nix ";
+
+/// Verbosity Nix logs at by default (lvlInfo), above which starting activities are not shown
+const INFO: u64 = 3;
+
+/// `FileTransfer` activity type, from Nix's libutil/logging.hh
+const ACT_FILE_TRANSFER: u64 = 101;
+
+/// `BuildLogLine` and `Progress` result types, from Nix's libutil/logging.hh
+const RES_BUILD_LOG_LINE: u64 = 101;
+const RES_PROGRESS: u64 = 105;
+
+/// Percentage steps download progress is shown at
+const PROGRESS_STEP: u64 = 25;
+
+/// Bytes Nix transferred over all processes, for reporting the bandwidth a run used
+static DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+
+pub fn downloaded() -> u64 {
+    DOWNLOADED.load(Ordering::Relaxed)
+}
+
+struct Download {
+    url: String,
+    done: u64,
+    shown: u64,
+}
+
+/// Structured log of one Nix process, tracking its downloads between lines
+#[derive(Default)]
+pub struct Log {
+    downloads: HashMap<u64, Download>,
+}
+
+/// Text with terminal escape sequences, which Nix colours hashes and paths with, removed
+fn strip_escapes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+
+        // CSI sequences end at their first letter
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        }
+    }
+
+    stripped
+}
+
+fn lines(text: &str) -> Vec<String> {
+    strip_escapes(text).lines().map(String::from).collect()
+}
+
+impl Log {
+    /// Human-readable lines for a line of Nix's stderr, with structured messages turned back into
+    /// the text Nix would have printed, download progress every `PROGRESS_STEP` percent and lines
+    /// that are not structured passed through
+    pub fn text(&mut self, line: &str) -> Vec<String> {
+        let Some(value) = line
+            .strip_prefix(PREFIX)
+            .and_then(|json| serde_json::from_str::<Value>(json).ok())
+        else {
+            return vec![line.to_string()];
+        };
+        let id = value["id"].as_u64().unwrap_or_default();
+
+        match value["action"].as_str() {
+            Some("msg") => lines(value["msg"].as_str().unwrap_or_default()),
+            Some("start") if value["type"].as_u64() == Some(ACT_FILE_TRANSFER) => {
+                let url = value["fields"][0].as_str().unwrap_or_default().to_string();
+                self.downloads.insert(
+                    id,
+                    Download {
+                        url,
+                        done: 0,
+                        shown: 0,
+                    },
+                );
+                lines(value["text"].as_str().unwrap_or_default())
+            }
+            Some("start") if value["level"].as_u64().is_some_and(|level| level <= INFO) => {
+                lines(value["text"].as_str().unwrap_or_default())
+            }
+            Some("result") if value["type"].as_u64() == Some(RES_BUILD_LOG_LINE) => {
+                lines(value["fields"][0].as_str().unwrap_or_default())
+            }
+            Some("result") if value["type"].as_u64() == Some(RES_PROGRESS) => {
+                let Some(download) = self.downloads.get_mut(&id) else {
+                    return vec![];
+                };
+                let done = value["fields"][0].as_u64().unwrap_or_default();
+                let expected = value["fields"][1].as_u64().unwrap_or_default();
+                download.done = done;
+
+                if expected == 0 {
+                    return vec![];
+                }
+                let step = done * 100 / expected / PROGRESS_STEP * PROGRESS_STEP;
+                if step <= download.shown || step >= 100 {
+                    return vec![];
+                }
+                download.shown = step;
+
+                vec![format!(
+                    "Downloading {}: {} of {} ({}%)",
+                    download.url,
+                    format_size(done),
+                    format_size(expected),
+                    step
+                )]
+            }
+            Some("stop") => match self.downloads.remove(&id) {
+                Some(download) => {
+                    DOWNLOADED.fetch_add(download.done, Ordering::Relaxed);
+                    vec![format!(
+                        "Downloaded {} ({})",
+                        download.url,
+                        format_size(download.done)
+                    )]
+                }
+                None => vec![],
+            },
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,1111 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::cache::DEFAULT_BINARY_CACHE;
+use crate::fetcher::FETCHERS;
+use crate::hydra::DEFAULT_HYDRA;
+use crate::mentions;
+use crate::plan;
+use crate::store;
+use crate::systemd;
+
+pub const USAGE: &str = "\
+Usage: nixpkgs_fod_reports [COMMAND] [OPTIONS] <NIXPKGS>
+       nixpkgs_fod_reports [COMMAND] [OPTIONS] --file <PATH>|--expr <EXPR> [<DIR>]
+       nixpkgs_fod_reports tag|untag [OPTIONS] <ATTR> <TAG>
+       nixpkgs_fod_reports history export [OPTIONS]
+       nixpkgs_fod_reports compare [OPTIONS] <NIXPKGS> <REV_A> <REV_B>
+       nixpkgs_fod_reports bisect --attr <ATTR> [OPTIONS] <NIXPKGS>
+
+Commands:
+  check                           Check FODs once and report on them (default)
+  daemon                          Keep checking FODs on a schedule, recording each run in the history
+  report serve                    Serve the reports in --dir over HTTP, without needing Nixpkgs
+  list-urls                       List every distinct upstream URL of the FODs with the attrs using them
+  compare                         List FODs added, removed or with a changed hash between two revisions, without fetching
+  bisect                          Find whether Nixpkgs or upstream changed for each mismatching FOD of --attr
+  tag, untag                      Add or remove a triage tag on an attr in the history, e.g. needs-review
+  history export                  Write checked and broken counts of each run in the history, overall and by fetcher, for charting
+  plan                            Print CI job definitions splitting the check into --shards shards
+
+Options:
+  --revision <REF>[,<REF>...]     Check these git revisions of NIXPKGS instead, e.g. release-24.11, broken down per revision (repeatable)
+  --jobset <PATH>                 Check the jobs of a Hydra jobset in NIXPKGS as Hydra enumerates them, e.g. pkgs/top-level/release.nix
+  --file <PATH>                   Check the derivations in a Nix file instead of Nixpkgs, e.g. ./ci.nix
+  --expr <EXPR>                   Check the derivations an expression evaluates to, e.g. '(import ./default.nix {}).myPackage'
+  --nixos-config <PATH|FLAKE#HOST> Check the FODs in the system closure of a NixOS configuration
+  --flake <REF>                   Check the FODs of a flake's outputs for the current system instead of Nixpkgs
+  --flake-outputs <NAME>[,...]    Flake outputs to check, e.g. packages, devShells or checks (default packages)
+  --cross <TARGET>[,<TARGET>...]  Also check FODs in pkgsCross.<TARGET> (repeatable)
+  --allow-unfree                  Evaluate with allowUnfree = true
+  --allow-insecure                Evaluate with insecure packages permitted
+  --allow-aliases                 Evaluate with allowAliases = true
+  --alias-pass                    Also enumerate with allowAliases = true, checking and reporting FODs only reachable through aliases
+  --nixpkgs-config <BINDING>      Extra Nixpkgs config binding, e.g. 'cudaSupport = true;' (repeatable)
+  --config-file <PATH>            Nixpkgs config file to merge into the generated config
+  --overlays <PATH>               Overlay file or directory to evaluate Nixpkgs with
+  --pass-env <VAR>                Pass VAR through to Nix processes (repeatable)
+  --no-default-pass-env           Do not pass proxy and certificate variables through by default
+  --proxy <URL>                   Proxy for all fetches, e.g. http://proxy:3128 or socks5h://localhost:1080
+  --no-proxy <HOST>[,<HOST>...]   Hosts to fetch from without the proxy (repeatable)
+  --netrc-file <PATH>             netrc file for authenticated fetches
+  --access-token <HOST>=<TOKEN>   Access token for a host (repeatable, defaults from GITHUB_TOKEN/GITLAB_TOKEN)
+  --substitute                    Allow substituting FOD outputs instead of fetching them from upstream
+  --binary-cache-check [URL]      Compare FOD outputs against a binary cache (default https://cache.nixos.org)
+  --hydra [URL]                   Compare failures against the latest successful Hydra build (default https://hydra.nixos.org)
+  --closure-of <ATTR>[,<ATTR>...] Only check FODs in the build closure of these attrs, e.g. firefox (repeatable)
+  --include-bootstrap             Also check the bootstrap tools and other stdenv seed FODs, which are left out by default
+  --max-depth <N>                 Only check FODs at most N inputs away from each attr, e.g. 1 for its direct sources
+  --fetcher <NAME>[,<NAME>...]    Only check FODs from these fetchers, e.g. fetchFromGitHub or fetchurl (repeatable)
+  --license <ID>[,<ID>...]        Only check attrs whose licenses are all free-only or these SPDX identifiers (repeatable)
+  --maintainer <HANDLE>[,...]     Only check attrs maintained by these GitHub users or teams (repeatable)
+  --preflight                     Check FOD URLs with HEAD requests before realising them
+  --skip-dead                     Skip realising FODs whose URLs are all dead (implies --preflight)
+  --outage-threshold <N>          Group failures from a host once N of its FODs fail (default 10, 0 to disable)
+  --swh                           Look up failing FODs in the Software Heritage archive
+  --swh-save                      Also submit save requests for failing git origins (implies --swh, uses SWH_TOKEN)
+  --wayback                       Look up Wayback Machine snapshots for dead URLs (implies --preflight)
+  --lint                          Warn about FODs pinned to mutable references
+  --lint-only                     Only lint FODs without realising them (implies --lint)
+  --confirm <N>                   Re-check failing FODs N more times before reporting them
+  --confirm-delay <SECONDS>       Delay before each re-check (default 60)
+  --broken-after <RUNS>           Consecutive failing runs before a FOD counts as confirmed broken rather than suspect (default 2)
+  --quarantine-stable-runs <N>    Quarantine attrs that passed and failed in their last N runs in the history (default 3, 0 to disable)
+  --quarantine-retries <N>        Extra re-checks for quarantined attrs on top of --confirm (default 2)
+  --double-fetch                  Fetch each FOD a second time straight after checking it, in a scratch store without substitutes, to catch content generated per request
+  --reclone                       Clone the pinned rev of git-based FODs with hash mismatches and hash its tree, telling changed archives from moved revs
+  --shuffle                       Check derivations in a random order, the same for the same --seed
+  --seed <N>                      Seed to shuffle with (default the current time, which is printed)
+  --limit <N>                     Only check the first N derivations in the check order
+  --start-after <ATTR|DRV>        Skip derivations up to and including ATTR or DRV in the check order, to resume a run
+  --schedule-by-size              Check the smallest FODs first, using sizes from previous runs and the binary cache
+  --max-fod-size <SIZE>           Skip FODs known to be larger than SIZE, e.g. 500M or 2G
+  --max-duration <DURATION>       Stop checking new derivations once the run took DURATION, e.g. 6h, and report the rest as not reached
+  --gc-initial-heap-size <SIZE>   Initial evaluator heap size (GC_INITIAL_HEAP_SIZE)
+  --max-eval-memory <SIZE>        Maximum evaluator heap size per Nix process (GC_MAXIMUM_HEAP_SIZE)
+  --no-eval-cache                 Evaluate with eval-cache = false
+  --min-free-space <SIZE>         Pause realisations and collect garbage when the store has less free space
+  --gc-max-freed <SIZE>           Space to free when collecting garbage (default twice --min-free-space)
+  --min-free <SIZE>               Have Nix collect garbage during builds when the store has less free space (min-free)
+  --max-free <SIZE>               Free space for Nix to stop collecting garbage at (max-free)
+  --nix-option <NAME> <VALUE>     Pass --option NAME VALUE to every Nix command (repeatable)
+  --nix-arg <ARG>                 Pass ARG to every Nix command (repeatable)
+  --nix-conf <PATH>               nix.conf fragment to pass to Nix commands through NIX_CONFIG
+  --nix-conf-line <LINE>          nix.conf line to pass to Nix commands, e.g. 'connect-timeout = 5' (repeatable)
+  --roots-dir <PATH>              Keep GC roots in PATH, removing those left by crashed runs (default a temporary directory)
+  --home <PATH>                   HOME for Nix commands, where Nix keeps its fetcher caches (default a temporary directory)
+  --cache-home <PATH>             XDG_CACHE_HOME for Nix commands (default .cache in their HOME)
+  --delete-batch <N>              Delete checked outputs from the store N at a time (default 100)
+  --no-delete                     Only unroot checked outputs, leaving them for garbage collection to free
+  --verify-store                  Verify the contents of realised outputs in the store before checking them
+  --repair                        Repair local outputs of FODs found not to be reproducible (needs a trusted user)
+  --store-access <MODE>           How to reach the Nix store: auto, daemon or local, checked before running (default auto)
+  --private-store <DIR>           Keep every store path in a private store under DIR, built in user namespaces, leaving the host's store alone
+  --nix-store-uri <URI>           Store URI to pass to every Nix command as is, e.g. unix:///run/nix/socket or local?root=/srv/store
+  --require-sandbox               Realise FODs in the sandbox and refuse to run if Nix would not use it
+  --fetch-jobs <N>                Check at most N derivations at once (default one per CPU)
+  --max-bandwidth <SIZE>          Limit downloads to about SIZE per second over all jobs, e.g. 10M
+  --abort-on-infra-errors         Abort the run after repeated infrastructure errors instead of continuing
+  --max-infra-errors <N>          Consecutive infrastructure errors to abort after (default 5)
+  --attr <ATTR>                   Attr to bisect
+  --since <REV>                   Revision to bisect from, whose hash matched upstream (default the last run it passed in the history)
+  --patches-dir <PATH>            Write patches updating the hashes of FODs whose upstream content changed to PATH
+  --open-prs <OWNER/REPO>         Push patches to a Nixpkgs fork and open draft PRs for them (needs --patches-dir, --confirm and GITHUB_TOKEN)
+  --pr-base <BRANCH>              Branch to open PRs against (default master)
+  --pr-message <TEMPLATE>         Commit message for PRs, with {attr}, {target}, {expected} and {got} filled in
+  --mentions <PATH>               Write a Markdown list of failing attrs by maintainer to mention in a tracking issue
+  --mentions-per-comment <N>      Maintainers to mention per comment in the list (default 50)
+  --format <FORMAT>               Report format: text, markdown, html, json, csv or r13y build responses (default text), json or csv for list-urls and history export (default json), or text or json for compare
+  --output <PATH>                 Write reports other than text, or URL lists, to PATH instead of standard output
+  --print-commands                Print each Nix command before running it, quoted for pasting into a shell
+  --audit-log <PATH>              Append every Nix command run, with its arguments, environment, exit code and duration, to PATH
+  --history <PATH>                Record each run's results in the history file at PATH (default in $STATE_DIRECTORY)
+  --notify-webhook <URL>          POST FODs breaking, being fixed or appearing to URL after each run (needs --history)
+  --submit <URL>                  POST each run's results and shard to an aggregation server at URL (needs --submit-token-file)
+  --submit-token-file <PATH>      File with the bearer token to submit with
+  --remind-after <DAYS>           Also notify again about failures every DAYS days they go on for
+  --ignore-file <PATH>            ignore.toml of known failures to report apart, with a reason and optional expiry date
+  --tofu <PATH>                   Record the content each FOD served in the database at PATH, warning when it changes later
+  --interval <DURATION>           Time between daemon runs, e.g. 6h or 1d (default 1d)
+  --listen <ADDR>                 Serve the daemon's JSON API over the history, or served reports, on ADDR (default 127.0.0.1:8080 for report serve)
+  --dir <PATH>                    Directory of reports for report serve
+  --follow <REF>                  Fetch REF from origin before each daemon run, only checking when it advanced
+  --shard <I>/<N>                 Only check the I-th of N shards of the attrs, e.g. 2/8
+  --shards <N>                    Shards for plan to split the attrs into (default 4)
+  --emit <FORMAT>                 Job definitions for plan: github-matrix or gitlab-yaml (default github-matrix)
+  -v, --verbose                   More output, -vv streams Nix output prefixed by what it is for
+  -q, --quiet                     Only print failures and the final summary
+  --color <WHEN>                  Color output: auto, always or never (default auto, respects NO_COLOR)
+  -h, --help                      Print this help
+";
+
+/// Schemes curl, and so Nix, can reach proxies with
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+
+const DEFAULT_PASS_ENV: &[&str] = &[
+    "http_proxy",
+    "https_proxy",
+    "ftp_proxy",
+    "all_proxy",
+    "no_proxy",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "FTP_PROXY",
+    "ALL_PROXY",
+    "NO_PROXY",
+    "NIX_SSL_CERT_FILE",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    "CURL_CA_BUNDLE",
+];
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub command: String,
+    /// Arguments as given, for plan to pass on to the shards
+    pub args: Vec<String>,
+    pub nixpkgs: PathBuf,
+    pub cross: Vec<String>,
+    pub allow_unfree: bool,
+    pub allow_insecure: bool,
+    pub allow_aliases: bool,
+    pub alias_pass: bool,
+    pub reclone: bool,
+    pub nixpkgs_config: Vec<String>,
+    pub config_file: Option<PathBuf>,
+    pub overlays: Option<PathBuf>,
+    pub pass_env: Vec<String>,
+    pub no_default_pass_env: bool,
+    pub proxy: Option<String>,
+    pub no_proxy: Vec<String>,
+    pub netrc_file: Option<PathBuf>,
+    pub access_tokens: Vec<String>,
+    pub substitute: bool,
+    pub binary_cache_check: Option<String>,
+    pub hydra: Option<String>,
+    pub closure_of: Vec<String>,
+    /// Git revisions of Nixpkgs to check together, instead of the checkout itself
+    pub revisions: Vec<String>,
+    /// Hydra jobset expression relative to Nixpkgs, to take the attrs from instead of nix-env
+    pub jobset: Option<PathBuf>,
+    pub file: Option<PathBuf>,
+    pub expr: Option<String>,
+    /// configuration.nix path or flake reference with a host, for checking a whole NixOS system
+    pub nixos_config: Option<String>,
+    pub flake: Option<String>,
+    pub flake_outputs: Vec<String>,
+    pub include_bootstrap: bool,
+    pub max_depth: Option<usize>,
+    pub fetchers: Vec<String>,
+    pub licenses: Vec<String>,
+    pub maintainers: Vec<String>,
+    pub preflight: bool,
+    pub skip_dead: bool,
+    pub outage_threshold: usize,
+    pub swh: bool,
+    pub swh_save: bool,
+    pub wayback: bool,
+    pub lint: bool,
+    pub lint_only: bool,
+    pub confirm: u32,
+    pub confirm_delay: u64,
+    pub broken_after: usize,
+    pub quarantine_stable_runs: usize,
+    pub quarantine_retries: u32,
+    pub double_fetch: bool,
+    pub shuffle: bool,
+    pub seed: Option<u64>,
+    pub limit: Option<usize>,
+    pub start_after: Option<String>,
+    pub schedule_by_size: bool,
+    pub max_fod_size: Option<u64>,
+    pub max_duration: Option<u64>,
+    pub gc_initial_heap_size: Option<u64>,
+    pub max_eval_memory: Option<u64>,
+    pub no_eval_cache: bool,
+    pub min_free_space: Option<u64>,
+    pub gc_max_freed: Option<u64>,
+    pub min_free: Option<u64>,
+    pub max_free: Option<u64>,
+    pub fetch_jobs: Option<usize>,
+    /// Bytes per second to download at most, shared out between the fetch jobs
+    pub max_bandwidth: Option<u64>,
+    pub nix_option: Vec<(String, String)>,
+    pub nix_args: Vec<String>,
+    pub nix_conf: Vec<String>,
+    pub roots_dir: Option<PathBuf>,
+    pub home: Option<PathBuf>,
+    pub cache_home: Option<PathBuf>,
+    pub delete_batch: usize,
+    pub no_delete: bool,
+    pub verify_store: bool,
+    pub repair: bool,
+    /// Store access mode as given, then the store Nix is told to use once checked
+    pub store_access: String,
+    pub private_store: Option<PathBuf>,
+    pub nix_store_uri: Option<String>,
+    pub require_sandbox: bool,
+    pub abort_on_infra_errors: bool,
+    pub max_infra_errors: usize,
+    pub patches_dir: Option<PathBuf>,
+    pub open_prs: Option<String>,
+    pub pr_base: String,
+    pub pr_message: Option<String>,
+    pub mentions: Option<PathBuf>,
+    pub mentions_per_comment: usize,
+    pub format: String,
+    pub output: Option<PathBuf>,
+    pub print_commands: bool,
+    pub audit_log: Option<PathBuf>,
+    pub history: Option<PathBuf>,
+    pub notify_webhook: Option<String>,
+    pub submit: Option<String>,
+    pub submit_token_file: Option<PathBuf>,
+    pub remind_after: Option<u64>,
+    pub ignore_file: Option<PathBuf>,
+    pub tofu: Option<PathBuf>,
+    pub interval: u64,
+    pub follow: Option<String>,
+    pub listen: Option<String>,
+    pub dir: Option<PathBuf>,
+    pub tag: Option<(String, String)>,
+    /// Old and new revisions for compare
+    pub compare: Option<(String, String)>,
+    pub attr: Option<String>,
+    pub since: Option<String>,
+    pub shard: Option<(usize, usize)>,
+    pub shards: usize,
+    pub emit: String,
+    pub verbose: u8,
+    pub quiet: bool,
+    pub color: String,
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    args.next().ok_or(anyhow!("Missing value for {}", flag))
+}
+
+/// Nix string literal for `value`, escaping anything that would end or interpolate into it
+fn nix_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("${", "\\${")
+    )
+}
+
+fn existing_path(value: String) -> Result<PathBuf> {
+    fs::canonicalize(&value).context(format!("Resolving path {}", value))
+}
+
+/// Flake reference with local paths made absolute, as only those can be fetched
+fn flake_ref(value: String) -> Result<String> {
+    if value.starts_with(['.', '/']) {
+        Ok(existing_path(value)?.display().to_string())
+    } else {
+        Ok(value)
+    }
+}
+
+fn number<T: std::str::FromStr>(value: &str, flag: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid number {} for {}", value, flag))
+}
+
+fn size(value: &str) -> Result<u64> {
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(value.len()),
+    );
+
+    let multiplier = match unit
+        .trim()
+        .to_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => bail!("Invalid size unit in {}", value),
+    };
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size {}", value))?;
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses a duration in seconds, optionally with an s, m, h or d suffix
+fn duration(value: &str) -> Result<u64> {
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+
+    let multiplier = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("Invalid duration unit in {}", value),
+    };
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration {}", value))?;
+
+    Ok(number * multiplier)
+}
+
+fn list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+}
+
+impl Options {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Option<Options>> {
+        let mut options = Options {
+            command: "check".to_string(),
+            outage_threshold: 10,
+            confirm_delay: 60,
+            broken_after: 2,
+            quarantine_stable_runs: 3,
+            quarantine_retries: 2,
+            max_infra_errors: 5,
+            delete_batch: 100,
+            pr_base: "master".to_string(),
+            mentions_per_comment: mentions::DEFAULT_PER_COMMENT,
+            format: "text".to_string(),
+            interval: 24 * 60 * 60,
+            shards: 4,
+            emit: "github-matrix".to_string(),
+            color: "auto".to_string(),
+            store_access: "auto".to_string(),
+            ..Default::default()
+        };
+        let mut nixpkgs = None;
+        let mut positional = Vec::new();
+        options.args = args.collect();
+        let mut args = options.args.clone().into_iter().peekable();
+
+        if let Some(command) = args.next_if(|arg| {
+            [
+                "check",
+                "daemon",
+                "report",
+                "list-urls",
+                "compare",
+                "bisect",
+                "plan",
+                "tag",
+                "untag",
+                "history",
+            ]
+            .contains(&arg.as_str())
+        }) {
+            options.command = command;
+        }
+        if options.command == "report" {
+            match args.next().as_deref() {
+                Some("serve") => options.command = "report serve".to_string(),
+                Some(command) => bail!("Unknown report command {}", command),
+                None => bail!("Missing report command"),
+            }
+        }
+        if options.command == "history" {
+            match args.next().as_deref() {
+                Some("export") => options.command = "history export".to_string(),
+                Some(command) => bail!("Unknown history command {}", command),
+                None => bail!("Missing history command"),
+            }
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--revision" => options.revisions.extend(list(&value(&mut args, &arg)?)),
+                "--jobset" => options.jobset = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--file" => options.file = Some(existing_path(value(&mut args, &arg)?)?),
+                "--expr" => options.expr = Some(value(&mut args, &arg)?),
+                "--nixos-config" => {
+                    let config = value(&mut args, &arg)?;
+                    // Flakes can only be fetched from absolute paths
+                    options.nixos_config = Some(match config.split_once('#') {
+                        Some((flake, host)) if flake.starts_with(['.', '/']) => {
+                            format!("{}#{}", existing_path(flake.to_string())?.display(), host)
+                        }
+                        Some(_) => config,
+                        None => existing_path(config)?.display().to_string(),
+                    });
+                }
+                "--flake" => options.flake = Some(flake_ref(value(&mut args, &arg)?)?),
+                "--flake-outputs" => options.flake_outputs.extend(list(&value(&mut args, &arg)?)),
+                "--cross" => options.cross.extend(list(&value(&mut args, &arg)?)),
+                "--allow-unfree" => options.allow_unfree = true,
+                "--allow-insecure" => options.allow_insecure = true,
+                "--allow-aliases" => options.allow_aliases = true,
+                "--alias-pass" => options.alias_pass = true,
+                "--reclone" => options.reclone = true,
+                "--nixpkgs-config" => options.nixpkgs_config.push(value(&mut args, &arg)?),
+                "--config-file" => {
+                    options.config_file = Some(existing_path(value(&mut args, &arg)?)?)
+                }
+                "--overlays" => options.overlays = Some(existing_path(value(&mut args, &arg)?)?),
+                "--pass-env" => options.pass_env.push(value(&mut args, &arg)?),
+                "--no-default-pass-env" => options.no_default_pass_env = true,
+                "--proxy" => {
+                    let proxy = value(&mut args, &arg)?;
+                    if !proxy
+                        .split_once("://")
+                        .is_some_and(|(scheme, _)| PROXY_SCHEMES.contains(&scheme))
+                    {
+                        bail!(
+                            "Invalid value {} for --proxy, expected a URL with one of {}",
+                            proxy,
+                            PROXY_SCHEMES.join(", ")
+                        );
+                    }
+                    options.proxy = Some(proxy);
+                }
+                "--no-proxy" => options.no_proxy.extend(list(&value(&mut args, &arg)?)),
+                "--netrc-file" => {
+                    options.netrc_file = Some(existing_path(value(&mut args, &arg)?)?)
+                }
+                "--access-token" => {
+                    let token = value(&mut args, &arg)?;
+                    if !token.contains('=') {
+                        bail!("Access token must be given as HOST=TOKEN");
+                    }
+                    options.access_tokens.push(token);
+                }
+                "--substitute" => options.substitute = true,
+                "--binary-cache-check" => {
+                    options.binary_cache_check = Some(
+                        args.next_if(|next| next.contains("://"))
+                            .unwrap_or(DEFAULT_BINARY_CACHE.to_string()),
+                    )
+                }
+                "--hydra" => {
+                    options.hydra = Some(
+                        args.next_if(|next| next.contains("://"))
+                            .unwrap_or(DEFAULT_HYDRA.to_string()),
+                    )
+                }
+                "--closure-of" => options.closure_of.extend(list(&value(&mut args, &arg)?)),
+                "--include-bootstrap" => options.include_bootstrap = true,
+                "--max-depth" => options.max_depth = Some(number(&value(&mut args, &arg)?, &arg)?),
+                "--fetcher" => {
+                    for fetcher in list(&value(&mut args, &arg)?) {
+                        if !FETCHERS.contains(&fetcher.as_str()) {
+                            bail!(
+                                "Unknown fetcher {}, expected one of {}",
+                                fetcher,
+                                FETCHERS.join(", ")
+                            );
+                        }
+                        options.fetchers.push(fetcher);
+                    }
+                }
+                "--license" => options.licenses.extend(list(&value(&mut args, &arg)?)),
+                "--maintainer" => options
+                    .maintainers
+                    .extend(list(&value(&mut args, &arg)?).map(|handle| {
+                        // Teams may be given with their organisation, as in @NixOS/rust
+                        handle
+                            .trim_start_matches('@')
+                            .rsplit('/')
+                            .next()
+                            .unwrap_or_default()
+                            .to_string()
+                    })),
+                "--preflight" => options.preflight = true,
+                "--skip-dead" => {
+                    options.preflight = true;
+                    options.skip_dead = true;
+                }
+                "--outage-threshold" => {
+                    options.outage_threshold = number(&value(&mut args, &arg)?, &arg)?
+                }
+                "--swh" => options.swh = true,
+                "--swh-save" => {
+                    options.swh = true;
+                    options.swh_save = true;
+                }
+                "--wayback" => {
+                    options.preflight = true;
+                    options.wayback = true;
+                }
+                "--lint" => options.lint = true,
+                "--lint-only" => {
+                    options.lint = true;
+                    options.lint_only = true;
+                }
+                "--confirm" => options.confirm = number(&value(&mut args, &arg)?, &arg)?,
+                "--confirm-delay" => {
+                    options.confirm_delay = number(&value(&mut args, &arg)?, &arg)?
+                }
+                "--broken-after" => {
+                    options.broken_after = number(&value(&mut args, &arg)?, &arg)?;
+                    if options.broken_after == 0 {
+                        bail!("Invalid value 0 for --broken-after");
+                    }
+                }
+                "--quarantine-stable-runs" => {
+                    options.quarantine_stable_runs = number(&value(&mut args, &arg)?, &arg)?
+                }
+                "--quarantine-retries" => {
+                    options.quarantine_retries = number(&value(&mut args, &arg)?, &arg)?
+                }
+                "--double-fetch" => options.double_fetch = true,
+                "--shuffle" => options.shuffle = true,
+                "--seed" => options.seed = Some(number(&value(&mut args, &arg)?, &arg)?),
+                "--limit" => options.limit = Some(number(&value(&mut args, &arg)?, &arg)?),
+                "--start-after" => options.start_after = Some(value(&mut args, &arg)?),
+                "--schedule-by-size" => options.schedule_by_size = true,
+                "--max-fod-size" => options.max_fod_size = Some(size(&value(&mut args, &arg)?)?),
+                "--max-duration" => {
+                    options.max_duration = Some(duration(&value(&mut args, &arg)?)?)
+                }
+                "--gc-initial-heap-size" => {
+                    options.gc_initial_heap_size = Some(size(&value(&mut args, &arg)?)?)
+                }
+                "--max-eval-memory" => {
+                    options.max_eval_memory = Some(size(&value(&mut args, &arg)?)?)
+                }
+                "--no-eval-cache" => options.no_eval_cache = true,
+                "--min-free-space" => {
+                    options.min_free_space = Some(size(&value(&mut args, &arg)?)?)
+                }
+                "--gc-max-freed" => options.gc_max_freed = Some(size(&value(&mut args, &arg)?)?),
+                "--min-free" => options.min_free = Some(size(&value(&mut args, &arg)?)?),
+                "--max-free" => options.max_free = Some(size(&value(&mut args, &arg)?)?),
+                "--fetch-jobs" => {
+                    let jobs = number(&value(&mut args, &arg)?, &arg)?;
+                    if jobs == 0 {
+                        bail!("Invalid value 0 for --fetch-jobs");
+                    }
+                    options.fetch_jobs = Some(jobs);
+                }
+                "--max-bandwidth" => options.max_bandwidth = Some(size(&value(&mut args, &arg)?)?),
+                "--nix-option" => {
+                    let name = value(&mut args, &arg)?;
+                    let value = value(&mut args, &arg)?;
+                    options.nix_option.push((name, value));
+                }
+                "--nix-arg" => options.nix_args.push(value(&mut args, &arg)?),
+                "--nix-conf" => {
+                    let path = value(&mut args, &arg)?;
+                    options.nix_conf.push(
+                        fs::read_to_string(&path)
+                            .context(format!("Reading nix.conf fragment {}", path))?,
+                    );
+                }
+                "--nix-conf-line" => options.nix_conf.push(value(&mut args, &arg)?),
+                "--roots-dir" => options.roots_dir = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--home" => options.home = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--cache-home" => options.cache_home = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--delete-batch" => options.delete_batch = number(&value(&mut args, &arg)?, &arg)?,
+                "--no-delete" => options.no_delete = true,
+                "--verify-store" => options.verify_store = true,
+                "--repair" => options.repair = true,
+                "--store-access" => {
+                    options.store_access = value(&mut args, &arg)?;
+                    if !store::ACCESS_MODES.contains(&options.store_access.as_str()) {
+                        bail!("Invalid value {} for --store-access", options.store_access);
+                    }
+                }
+                "--private-store" => {
+                    options.private_store = Some(PathBuf::from(value(&mut args, &arg)?))
+                }
+                "--nix-store-uri" => options.nix_store_uri = Some(value(&mut args, &arg)?),
+                "--require-sandbox" => options.require_sandbox = true,
+                "--abort-on-infra-errors" => options.abort_on_infra_errors = true,
+                "--max-infra-errors" => {
+                    options.max_infra_errors = number(&value(&mut args, &arg)?, &arg)?
+                }
+                "--attr" => options.attr = Some(value(&mut args, &arg)?),
+                "--since" => options.since = Some(value(&mut args, &arg)?),
+                "--patches-dir" => {
+                    options.patches_dir = Some(PathBuf::from(value(&mut args, &arg)?))
+                }
+                "--open-prs" => {
+                    let fork = value(&mut args, &arg)?;
+                    if fork.split('/').count() != 2 {
+                        bail!("Fork for --open-prs must be given as OWNER/REPO");
+                    }
+                    options.open_prs = Some(fork);
+                }
+                "--pr-base" => options.pr_base = value(&mut args, &arg)?,
+                "--pr-message" => options.pr_message = Some(value(&mut args, &arg)?),
+                "--mentions" => options.mentions = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--mentions-per-comment" => {
+                    options.mentions_per_comment = number(&value(&mut args, &arg)?, &arg)?
+                }
+                "--format" => {
+                    options.format = value(&mut args, &arg)?;
+                    if !["text", "markdown", "html", "r13y", "json", "csv"]
+                        .contains(&options.format.as_str())
+                    {
+                        bail!("Invalid value {} for --format", options.format);
+                    }
+                }
+                "--output" => options.output = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--print-commands" => options.print_commands = true,
+                "--audit-log" => options.audit_log = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--history" => options.history = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--notify-webhook" => options.notify_webhook = Some(value(&mut args, &arg)?),
+                "--submit" => options.submit = Some(value(&mut args, &arg)?),
+                "--submit-token-file" => {
+                    options.submit_token_file = Some(existing_path(value(&mut args, &arg)?)?)
+                }
+                "--remind-after" => {
+                    options.remind_after = Some(number(&value(&mut args, &arg)?, &arg)?)
+                }
+                "--ignore-file" => {
+                    options.ignore_file = Some(existing_path(value(&mut args, &arg)?)?)
+                }
+                "--tofu" => options.tofu = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--interval" => options.interval = duration(&value(&mut args, &arg)?)?,
+                "--follow" => options.follow = Some(value(&mut args, &arg)?),
+                "--listen" => options.listen = Some(value(&mut args, &arg)?),
+                "--dir" => options.dir = Some(existing_path(value(&mut args, &arg)?)?),
+                "--shard" => {
+                    let shard = value(&mut args, &arg)?;
+                    options.shard = match shard.split_once('/') {
+                        Some((i, n)) => Some((number(i, &arg)?, number(n, &arg)?)),
+                        None => bail!("Invalid value {} for --shard, expected I/N", shard),
+                    };
+                    if let Some((i, n)) = options.shard {
+                        if i == 0 || i > n {
+                            bail!("Invalid value {} for --shard, expected 1 <= I <= N", shard);
+                        }
+                    }
+                }
+                "--shards" => {
+                    options.shards = number(&value(&mut args, &arg)?, &arg)?;
+                    if options.shards == 0 {
+                        bail!("Invalid value 0 for --shards");
+                    }
+                }
+                "--emit" => {
+                    options.emit = value(&mut args, &arg)?;
+                    if !plan::FORMATS.contains(&options.emit.as_str()) {
+                        bail!("Invalid value {} for --emit", options.emit);
+                    }
+                }
+                "-v" | "--verbose" => options.verbose += 1,
+                "-vv" => options.verbose += 2,
+                "-q" | "--quiet" => options.quiet = true,
+                "--color" => {
+                    options.color = value(&mut args, &arg)?;
+                    if !["auto", "always", "never"].contains(&options.color.as_str()) {
+                        bail!("Invalid value {} for --color", options.color);
+                    }
+                }
+                _ if arg.starts_with('-') => bail!("Unknown option {}", arg),
+                _ if options.command == "tag" || options.command == "untag" => positional.push(arg),
+                _ if nixpkgs.is_none() => nixpkgs = Some(PathBuf::from(arg)),
+                _ if options.command == "compare" => positional.push(arg),
+                _ => bail!("Unexpected argument {}", arg),
+            }
+        }
+
+        if options.history.is_none() {
+            options.history = systemd::state_directory().map(|dir| dir.join("history.jsonl"));
+        }
+
+        let formats: &[&str] =
+            if options.command == "list-urls" || options.command == "history export" {
+                if options.format == "text" {
+                    options.format = "json".to_string();
+                }
+                &["json", "csv"]
+            } else if options.command == "compare" {
+                &["text", "json"]
+            } else if options.command == "bisect" {
+                &["text"]
+            } else {
+                &["text", "markdown", "html", "r13y", "json", "csv"]
+            };
+        if !formats.contains(&options.format.as_str()) {
+            bail!(
+                "Invalid value {} for --format with {}",
+                options.format,
+                options.command
+            );
+        }
+
+        if options.shuffle && options.schedule_by_size {
+            bail!("--shuffle and --schedule-by-size each decide the check order, so use only one");
+        }
+
+        let targets = [
+            options.jobset.is_some(),
+            options.file.is_some(),
+            options.expr.is_some(),
+            options.nixos_config.is_some(),
+            options.flake.is_some(),
+        ];
+        if targets.iter().filter(|given| **given).count() > 1 {
+            bail!("Only one of --jobset, --file, --expr, --nixos-config and --flake can be given");
+        }
+        if targets.contains(&true) && !options.cross.is_empty() {
+            bail!("--cross enumerates pkgsCross in Nixpkgs, so cannot be used with --jobset, --file, --expr, --nixos-config or --flake");
+        }
+        if options.alias_pass && options.allow_aliases {
+            bail!("--alias-pass already enumerates with aliases allowed, so cannot be used with --allow-aliases");
+        }
+        if options.alias_pass && !options.revisions.is_empty() {
+            bail!("--alias-pass cannot be used with --revision");
+        }
+        if options.flake_outputs.is_empty() {
+            options.flake_outputs.push("packages".to_string());
+        }
+
+        if options.command == "plan" && options.shard.is_some() {
+            bail!("plan splits every attr into shards itself, so cannot take --shard");
+        }
+
+        // Transitions are found by comparing against the previous run
+        if options.notify_webhook.is_some() && options.history.is_none() {
+            bail!("--notify-webhook needs --history to tell what changed since the last run");
+        }
+
+        if options.submit.is_some() && options.submit_token_file.is_none() {
+            bail!("--submit needs --submit-token-file to authenticate with");
+        }
+
+        if options.private_store.is_some() && options.store_access != "auto" {
+            bail!("--private-store is a store of its own, so cannot be used with --store-access");
+        }
+        if options.nix_store_uri.is_some()
+            && (options.private_store.is_some() || options.store_access != "auto")
+        {
+            bail!("--nix-store-uri picks the store itself, so cannot be used with --store-access or --private-store");
+        }
+
+        if options.command == "daemon" && options.history.is_none() {
+            bail!("daemon needs --history to record its runs in");
+        }
+
+        if options.open_prs.is_some() {
+            if options.patches_dir.is_none() {
+                bail!("--open-prs needs --patches-dir to write the patches to");
+            }
+            // Content seen only once may be a man-in-the-middle rather than upstream changing
+            if options.confirm == 0 {
+                bail!("--open-prs needs --confirm so only repeatedly seen content is proposed");
+            }
+        }
+
+        for (var, host) in [
+            ("GITHUB_TOKEN", "github.com"),
+            ("GITLAB_TOKEN", "gitlab.com"),
+        ] {
+            let prefix = format!("{}=", host);
+            if let Ok(token) = env::var(var) {
+                if !token.is_empty()
+                    && !options.access_tokens.iter().any(|t| t.starts_with(&prefix))
+                {
+                    options.access_tokens.push(format!("{}{}", prefix, token));
+                }
+            }
+        }
+
+        if options.command == "report serve" {
+            if options.dir.is_none() {
+                bail!("report serve needs --dir to serve reports from");
+            }
+        } else if options.command == "history export" {
+            if options.history.is_none() {
+                bail!("history export needs --history to export from");
+            }
+        } else if options.command == "tag" || options.command == "untag" {
+            let [attr, tag] = <[String; 2]>::try_from(positional)
+                .map_err(|_| anyhow!("{} needs an attr and a tag", options.command))?;
+            if options.history.is_none() {
+                bail!("{} needs --history to record tags in", options.command);
+            }
+            options.tag = Some((attr, tag));
+        } else if options.command == "compare" {
+            let [old, new] = <[String; 2]>::try_from(positional)
+                .map_err(|_| anyhow!("compare needs two revisions to compare"))?;
+            options.compare = Some((old, new));
+            options.nixpkgs = nixpkgs.ok_or(anyhow!("Missing path to Nixpkgs"))?;
+        } else if options.command == "bisect" {
+            if options.attr.is_none() {
+                bail!("bisect needs --attr to bisect");
+            }
+            options.nixpkgs = nixpkgs.ok_or(anyhow!("Missing path to Nixpkgs"))?;
+        } else if options.file.is_some() || options.expr.is_some() || options.uses_flakes() {
+            // Other repositories are evaluated from the current directory unless given
+            options.nixpkgs = match nixpkgs {
+                Some(dir) => dir,
+                None => env::current_dir().context("Finding current directory")?,
+            };
+        } else {
+            options.nixpkgs = nixpkgs.ok_or(anyhow!("Missing path to Nixpkgs"))?;
+        }
+
+        Ok(Some(options))
+    }
+
+    pub fn nixpkgs_config(&self) -> String {
+        let mut config = vec![format!("allowAliases = {};", self.allow_aliases)];
+
+        if self.allow_unfree {
+            config.push("allowUnfree = true;".to_string());
+        }
+        if self.allow_insecure {
+            config.push("allowInsecurePredicate = _: true;".to_string());
+        }
+        config.extend(self.nixpkgs_config.iter().cloned());
+
+        // Imported through the search path `nix_path` gives it, as a path pasted into the
+        // expression would have to be valid Nix path syntax
+        match &self.config_file {
+            Some(_) => format!(
+                "args: let user = import <nixpkgs-config>; in (if builtins.isFunction user then user args else user) // {{ {} }}",
+                config.join(" ")
+            ),
+            None => format!("{{ {} }}", config.join(" ")),
+        }
+    }
+
+    /// Binary cache to look up cached sizes in, as used for the cross-check if there is one
+    pub fn size_cache_url(&self) -> &str {
+        self.binary_cache_check
+            .as_deref()
+            .unwrap_or(DEFAULT_BINARY_CACHE)
+    }
+
+    pub fn nix_options(&self) -> Vec<(String, String)> {
+        let mut nix_options = vec![("restrict-eval".to_string(), "true".to_string())];
+
+        if let Some(netrc_file) = &self.netrc_file {
+            nix_options.push((
+                "netrc-file".to_string(),
+                netrc_file.to_str().expect("Path to string").to_string(),
+            ));
+        }
+        if self.store_access != "auto" {
+            nix_options.push(("store".to_string(), self.store_access.clone()));
+        }
+        if let Some(min_free) = self.min_free {
+            nix_options.push(("min-free".to_string(), min_free.to_string()));
+        }
+        if let Some(max_free) = self.max_free {
+            nix_options.push(("max-free".to_string(), max_free.to_string()));
+        }
+        if let Some(bandwidth) = self.job_bandwidth() {
+            // download-speed is in KiB/s, where 0 means unlimited
+            nix_options.push((
+                "download-speed".to_string(),
+                (bandwidth / 1024).max(1).to_string(),
+            ));
+        }
+        nix_options.extend(self.nix_option.iter().cloned());
+
+        nix_options
+    }
+
+    pub fn fetch_jobs(&self) -> usize {
+        self.fetch_jobs.unwrap_or_else(rayon::current_num_threads)
+    }
+
+    /// Bytes per second each fetch job may download at under --max-bandwidth
+    pub fn job_bandwidth(&self) -> Option<u64> {
+        self.max_bandwidth
+            .map(|bandwidth| (bandwidth / self.fetch_jobs() as u64).max(1))
+    }
+
+    /// Environment for processes that evaluate Nixpkgs
+    pub fn eval_env(&self) -> Vec<(&'static str, String)> {
+        let mut eval_env = Vec::new();
+
+        if let Some(size) = self.gc_initial_heap_size {
+            eval_env.push(("GC_INITIAL_HEAP_SIZE", size.to_string()));
+        }
+        if let Some(size) = self.max_eval_memory {
+            eval_env.push(("GC_MAXIMUM_HEAP_SIZE", size.to_string()));
+        }
+
+        eval_env
+    }
+
+    /// Options for processes that evaluate Nixpkgs
+    pub fn eval_options(&self) -> Vec<(String, String)> {
+        let mut eval_options = Vec::new();
+
+        if self.no_eval_cache {
+            eval_options.push(("eval-cache".to_string(), "false".to_string()));
+        }
+        if self.uses_flakes() {
+            eval_options.push((
+                "extra-experimental-features".to_string(),
+                "flakes".to_string(),
+            ));
+        }
+
+        eval_options
+    }
+
+    /// Configuration passed in `NIX_CONFIG`, which carries the access tokens too as anything in
+    /// arguments can be read by every local user, set last so they win over --nix-conf
+    pub fn nix_config(&self) -> Option<String> {
+        let mut lines = self
+            .nix_conf
+            .iter()
+            .map(|fragment| fragment.trim_end().to_string())
+            .collect::<Vec<String>>();
+        if !self.access_tokens.is_empty() {
+            lines.push(format!("access-tokens = {}", self.access_tokens.join(" ")));
+        }
+
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    pub fn pass_env(&self) -> impl Iterator<Item = &str> {
+        let defaults = if self.no_default_pass_env {
+            &[][..]
+        } else {
+            DEFAULT_PASS_ENV
+        };
+
+        defaults
+            .iter()
+            .copied()
+            .chain(self.pass_env.iter().map(String::as_str))
+    }
+
+    /// Proxy variables for --proxy and --no-proxy, in both of the cases tools read them in
+    pub fn proxy_env(&self) -> Vec<(String, String)> {
+        let mut proxy_env = Vec::new();
+
+        if let Some(proxy) = &self.proxy {
+            for var in ["http_proxy", "https_proxy", "ftp_proxy", "all_proxy"] {
+                proxy_env.push((var.to_string(), proxy.clone()));
+                proxy_env.push((var.to_uppercase(), proxy.clone()));
+            }
+        }
+        if !self.no_proxy.is_empty() {
+            let no_proxy = self.no_proxy.join(",");
+            proxy_env.push(("no_proxy".to_string(), no_proxy.clone()));
+            proxy_env.push(("NO_PROXY".to_string(), no_proxy));
+        }
+
+        proxy_env
+    }
+
+    /// Whether the target is taken from a flake, so evaluation needs flakes enabled
+    fn uses_flakes(&self) -> bool {
+        self.flake.is_some() || self.nixos_config.iter().any(|config| config.contains('#'))
+    }
+
+    /// Arguments selecting what nix-instantiate evaluates attrs from, relative to Nixpkgs
+    pub fn entry(&self) -> Vec<String> {
+        if let Some(flake) = &self.flake {
+            let outputs = self
+                .flake_outputs
+                .iter()
+                .map(|output| {
+                    format!(
+                        "{0} = flake.{0}.${{builtins.currentSystem}} or {{ }};",
+                        nix_string(output)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+
+            return vec![
+                "--expr".to_string(),
+                format!(
+                    "let flake = builtins.getFlake {}; in {{ {} }}",
+                    nix_string(flake),
+                    outputs
+                ),
+            ];
+        }
+
+        if let Some(config) = &self.nixos_config {
+            let system = match config.split_once('#') {
+                Some((flake, host)) => format!(
+                    "(builtins.getFlake {}).nixosConfigurations.{}",
+                    nix_string(flake),
+                    nix_string(host)
+                ),
+                None => format!(
+                    "import ./nixos {{ configuration = /. + {}; }}",
+                    nix_string(config)
+                ),
+            };
+
+            return vec![
+                "--expr".to_string(),
+                format!("{{ system = ({}).config.system.build.toplevel; }}", system),
+            ];
+        }
+
+        if let Some(expr) = &self.expr {
+            // A lone derivation gets an attr of its own, as attrs are instantiated with -A
+            return vec![
+                "--expr".to_string(),
+                format!(
+                    "let target = {}; in if builtins.isAttrs target && target.type or null == \"derivation\" then {{ expr = target; }} else target",
+                    expr
+                ),
+            ];
+        }
+
+        let file = self.file.as_ref().or(self.jobset.as_ref());
+        vec![file.map_or(".".to_string(), |file| file.display().to_string())]
+    }
+
+    /// Expression to list jobs from the way Hydra would, when not enumerating Nixpkgs with nix-env
+    pub fn target(&self) -> Option<String> {
+        if self.expr.is_some() || self.flake.is_some() {
+            return Some(format!("({})", self.entry()[1]));
+        }
+
+        let file = self.file.as_ref().or(self.jobset.as_ref())?;
+        Some(if file.is_absolute() {
+            format!("import {}", file.display())
+        } else {
+            format!("import ./{}", file.display())
+        })
+    }
+
+    pub fn nix_path(&self, path: &[&Path]) -> String {
+        let mut nix_path = path
+            .iter()
+            .map(|p| p.to_str().expect("Path to string").to_string())
+            .collect::<Vec<String>>();
+
+        if !path.is_empty() {
+            // Named entries are also allowed under restrict-eval
+            if let Some(overlays) = &self.overlays {
+                nix_path.push(format!("nixpkgs-overlays={}", overlays.display()));
+            }
+            if let Some(config_file) = &self.config_file {
+                nix_path.push(format!("nixpkgs-config={}", config_file.display()));
+            }
+        }
+
+        nix_path.join(":")
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::hash::{Encoding, Hash};
+use crate::report::{FodResult, Status};
+
+/// Lines of context around each change, as diff -u uses
+const CONTEXT: usize = 3;
+
+/// Whether a mismatch looks like upstream legitimately changed, rather than serving different
+/// content on each fetch or having only failed transiently
+pub fn is_candidate(result: &FodResult) -> bool {
+    result.status == Status::NotReproducible && result.got_hash.is_some() && !result.flaky
+}
+
+/// Nix expressions and the JSON files some of them read their hashes from
+fn sources(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).context(format!("Listing {}", dir.display()))? {
+        let path = entry.context("Reading directory entry")?.path();
+
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            sources(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "nix" || extension == "json")
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Unified diff between two versions of a file that only differ within lines
+fn diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines = old.lines().collect::<Vec<&str>>();
+    let new_lines = new.lines().collect::<Vec<&str>>();
+    let missing_newline = !old.ends_with('\n');
+
+    let mut hunks = Vec::<(usize, usize)>::new();
+    for i in (0..old_lines.len()).filter(|&i| old_lines[i] != new_lines[i]) {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + CONTEXT + 1).min(old_lines.len());
+
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut diff = format!("--- a/{}\n+++ b/{}\n", path, path);
+    for (start, end) in hunks {
+        let len = end - start;
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            start + 1,
+            len,
+            start + 1,
+            len
+        ));
+
+        for i in start..end {
+            let last = missing_newline && i == old_lines.len() - 1;
+            let no_newline = if last {
+                "\n\\ No newline at end of file"
+            } else {
+                ""
+            };
+
+            if old_lines[i] == new_lines[i] {
+                diff.push_str(&format!(" {}{}\n", old_lines[i], no_newline));
+            } else {
+                diff.push_str(&format!("-{}{}\n", old_lines[i], no_newline));
+                diff.push_str(&format!("+{}{}\n", new_lines[i], no_newline));
+            }
+        }
+    }
+
+    diff
+}
+
+/// `contents` with every spelling of `expected` replaced by `got` spelled the same way
+fn update(contents: &str, expected: &Hash, got: &Hash) -> Option<String> {
+    let mut updated = contents.to_string();
+
+    // Longer spellings go first so a prefixed hash is not replaced as its bare digest
+    for (encoding, spelling) in expected.spellings() {
+        updated = updated.replace(&spelling, &got.encode(encoding));
+    }
+
+    (updated != contents).then_some(updated)
+}
+
+/// Writes a patch per FOD whose upstream content changed, updating its hash wherever Nixpkgs
+/// pins it, and returns the patches written
+pub fn write<'a>(
+    nixpkgs: &Path,
+    patches_dir: &Path,
+    results: &'a [FodResult],
+) -> Result<Vec<(&'a FodResult, PathBuf)>> {
+    let candidates = results
+        .iter()
+        .filter(|result| is_candidate(result))
+        .filter_map(|result| {
+            let expected = Hash::parse(&result.expected_hash, None)?;
+            let got = Hash::parse(result.got_hash.as_ref()?, Some(&expected.algo))?;
+            Some((result, expected, got))
+        })
+        .collect::<Vec<(&FodResult, Hash, Hash)>>();
+
+    if candidates.is_empty() {
+        return Ok(vec![]);
+    }
+
+    fs::create_dir_all(patches_dir).context(format!(
+        "Creating patches directory {}",
+        patches_dir.display()
+    ))?;
+
+    // The FOD may come from a dependency of the attr it was found through, so its meta.position
+    // cannot be relied on and the whole tree is searched instead
+    let mut files = Vec::new();
+    sources(nixpkgs, &mut files)?;
+
+    let mut written = Vec::new();
+
+    for (result, expected, got) in candidates {
+        let diffs = files
+            .par_iter()
+            .filter_map(|file| {
+                let contents = fs::read_to_string(file).ok()?;
+                let updated = update(&contents, &expected, &got)?;
+                let path = file.strip_prefix(nixpkgs).unwrap_or(file);
+
+                Some(diff(&path.to_string_lossy(), &contents, &updated))
+            })
+            .collect::<Vec<String>>();
+
+        if diffs.is_empty() {
+            warn!(
+                "Could not find hash {} of {} in Nixpkgs, not writing a patch",
+                result.expected_hash,
+                result.target()
+            );
+            continue;
+        }
+
+        let name = result
+            .drv
+            .file_stem()
+            .expect("Derivation name")
+            .to_string_lossy()
+            .into_owned();
+        let patch_path = patches_dir.join(format!("{}.patch", name));
+
+        fs::write(
+            &patch_path,
+            format!(
+                "Update hash of FOD {} from {}\n\nUpstream now serves {} instead of {}.\n\n{}",
+                result.target(),
+                result.attr,
+                got.encode(Encoding::Sri),
+                result.expected_hash,
+                diffs.concat()
+            ),
+        )
+        .context(format!("Writing patch {}", patch_path.display()))?;
+
+        written.push((result, patch_path));
+    }
+
+    Ok(written)
+}
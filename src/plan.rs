@@ -0,0 +1,92 @@
+use serde_json::json;
+
+pub const FORMATS: &[&str] = &["github-matrix", "gitlab-yaml"];
+
+/// Stable shard for an attr, so attrs stay in their shard as others are added and removed
+pub fn shard_of(attr: &str, shards: usize) -> usize {
+    // FNV-1a, as the standard library's hasher is not guaranteed to be stable across releases
+    let hash = attr.bytes().fold(0xcbf29ce484222325u64, |hash, c| {
+        (hash ^ c as u64).wrapping_mul(0x100000001b3)
+    });
+
+    (hash % shards as u64) as usize + 1
+}
+
+/// Argument quoted for POSIX shells where needed
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@+%".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Arguments for a shard's run, being the plan's own arguments without the plan-only ones
+fn shard_args(args: &[String], shard: usize, shards: usize) -> Vec<String> {
+    let mut shard_args = vec!["check".to_string()];
+
+    let mut args = args.iter().peekable();
+    args.next_if(|arg| *arg == "plan");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--emit" | "--shards" | "--shard" => {
+                args.next();
+            }
+            _ => shard_args.push(arg.clone()),
+        }
+    }
+
+    shard_args.extend(["--shard".to_string(), format!("{}/{}", shard, shards)]);
+
+    shard_args
+}
+
+fn command(args: &[String], shard: usize, shards: usize) -> String {
+    ["nixpkgs_fod_reports".to_string()]
+        .into_iter()
+        .chain(shard_args(args, shard, shards))
+        .map(|arg| quote(&arg))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// CI job definitions running one shard each, with `counts[i]` attrs in shard `i + 1`
+pub fn emit(format: &str, args: &[String], counts: &[usize]) -> String {
+    let shards = counts.len();
+
+    match format {
+        "gitlab-yaml" => counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                format!(
+                    "# {} attrs\nfod-check-{}-of-{}:\n  script:\n    - {}\n",
+                    count,
+                    i + 1,
+                    shards,
+                    serde_json::to_string(&command(args, i + 1, shards))
+                        .expect("Serializing command")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        _ => format!(
+            "{}\n",
+            json!({
+                "include": counts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, count)| json!({
+                        "shard": format!("{}/{}", i + 1, shards),
+                        "attrs": count,
+                        "command": command(args, i + 1, shards),
+                    }))
+                    .collect::<Vec<serde_json::Value>>(),
+            })
+        ),
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use tempfile::tempdir;
+
+use crate::git::git;
+use crate::github::{self, NIXPKGS_REPO};
+use crate::options::Options;
+use crate::report::FodResult;
+
+const DEFAULT_MESSAGE: &str = "\
+{attr}: update source hash
+
+Upstream now serves {got} for {target} instead of the pinned {expected}.";
+
+/// Supplies the GitHub token to git from the environment, so it never appears in arguments
+const CREDENTIAL_HELPER: &str =
+    "credential.helper=!f() { echo username=x-access-token; echo \"password=$GITHUB_TOKEN\"; }; f";
+
+fn message(template: &str, result: &FodResult) -> String {
+    template
+        .replace("{attr}", &result.attr)
+        .replace("{target}", &result.target())
+        .replace("{expected}", &result.expected_hash)
+        .replace("{got}", result.got_hash.as_deref().unwrap_or_default())
+}
+
+fn branch(result: &FodResult) -> String {
+    let drv_name = result
+        .drv
+        .file_name()
+        .expect("Derivation name")
+        .to_string_lossy();
+
+    format!(
+        "fod-reports/{}-{}",
+        result.attr.replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '.',
+            "-"
+        ),
+        &drv_name[..8.min(drv_name.len())]
+    )
+}
+
+/// Commits `patch` on a branch of the checked revision, pushes it to `fork` and opens a draft
+/// pull request for it against Nixpkgs
+pub fn open(
+    opts: &Options,
+    fork: &str,
+    result: &FodResult,
+    patch: &Path,
+    maintainers: &[String],
+) -> Result<String> {
+    let nixpkgs = opts.nixpkgs.as_path();
+    let branch = branch(result);
+    let message = message(
+        opts.pr_message.as_deref().unwrap_or(DEFAULT_MESSAGE),
+        result,
+    );
+
+    // A separate worktree leaves the checkout being evaluated untouched
+    let worktree_dir = tempdir().context("Creating temporary directory for worktree")?;
+    let worktree = worktree_dir.path().join("nixpkgs");
+    let worktree_str = worktree.to_str().expect("Path to string");
+
+    git(
+        nixpkgs,
+        &["worktree", "add", "-B", &branch, worktree_str, "HEAD"],
+    )
+    .context("Creating worktree")?;
+
+    let pushed = git(
+        &worktree,
+        &["apply", "--index", patch.to_str().expect("Path to string")],
+    )
+    .context(format!("Applying {}", patch.display()))
+    .and_then(|_| git(&worktree, &["commit", "--quiet", "-m", &message]).context("Committing"))
+    .and_then(|_| {
+        git(
+            &worktree,
+            &[
+                "-c",
+                "credential.helper=",
+                "-c",
+                CREDENTIAL_HELPER,
+                "push",
+                "--force",
+                &format!("https://github.com/{}.git", fork),
+                &format!("HEAD:refs/heads/{}", branch),
+            ],
+        )
+        .context(format!("Pushing {} to {}", branch, fork))
+    });
+
+    if let Err(err) = git(nixpkgs, &["worktree", "remove", "--force", worktree_str])
+        .and_then(|_| git(nixpkgs, &["branch", "-D", &branch]))
+    {
+        warn!(
+            "Error cleaning up worktree for {}, ignoring: {:#}",
+            branch, err
+        );
+    }
+
+    pushed?;
+
+    let (title, description) = message.split_once('\n').unwrap_or((&message, ""));
+
+    let mut body = description.trim().to_string();
+    body.push_str(&format!(
+        "\n\nUpstream served the same content on {} re-checks, but the change itself still needs \
+         reviewing before merging.",
+        opts.confirm
+    ));
+    if !maintainers.is_empty() {
+        body.push_str(&format!(
+            "\n\ncc {}",
+            maintainers
+                .iter()
+                .map(|maintainer| format!("@{}", maintainer))
+                .collect::<Vec<String>>()
+                .join(" ")
+        ));
+    }
+
+    let owner = fork.split('/').next().unwrap_or(fork);
+
+    github::create_draft_pull(
+        NIXPKGS_REPO,
+        &format!("{}:{}", owner, branch),
+        &opts.pr_base,
+        title,
+        &body,
+    )
+}
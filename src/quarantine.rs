@@ -0,0 +1,41 @@
+use std::collections::BTreeSet;
+
+use crate::history::{History, Record};
+
+/// Attrs whose FODs went between passing and failing, or only passed on a retry, within their
+/// last `stable_runs` runs, so they stay quarantined until that many runs agree
+pub fn quarantined(history: &History, stable_runs: usize) -> BTreeSet<String> {
+    let mut quarantined = BTreeSet::new();
+    if stable_runs == 0 {
+        return quarantined;
+    }
+
+    let Some(latest) = history.latest() else {
+        return quarantined;
+    };
+
+    for record in &latest.records {
+        let recent = history
+            .runs
+            .iter()
+            .rev()
+            .filter_map(|run| {
+                run.records
+                    .iter()
+                    .find(|r| r.attr == record.attr && r.output == record.output)
+            })
+            .take(stable_runs + 1)
+            .collect::<Vec<&Record>>();
+
+        let unstable = recent.iter().any(|r| r.flaky)
+            || recent
+                .windows(2)
+                .any(|pair| pair[0].status.is_failure() != pair[1].status.is_failure());
+
+        if unstable {
+            quarantined.insert(record.attr.clone());
+        }
+    }
+
+    quarantined
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::report::{FodResult, Report, Status};
+
+/// r13y's build status for a derivation, serialized the way its serde enums are
+fn status(results: &[&FodResult]) -> Option<Value> {
+    if results
+        .iter()
+        .all(|result| result.status == Status::Reproducible)
+    {
+        return Some(json!("Reproducible"));
+    }
+
+    // Both fetches are needed to tell whether the content is reproducible
+    if results
+        .iter()
+        .any(|result| matches!(result.status, Status::FetchFailed | Status::DeadUrl))
+    {
+        return Some(json!("FirstFailed"));
+    }
+
+    let unreproducible = results
+        .iter()
+        .filter(|result| {
+            matches!(
+                result.status,
+                Status::NotReproducible | Status::Nondeterministic
+            )
+        })
+        .filter_map(|result| {
+            // r13y wants both hashes it got, which two fetches in a row give for nondeterministic
+            // content rather than the recorded hash
+            let hashes = match &result.double_fetched {
+                Some((first, second)) => json!([first, second]),
+                None => json!([result.expected_hash, result.got_hash.as_ref()?]),
+            };
+            Some((result.output.clone(), hashes))
+        })
+        .collect::<serde_json::Map<String, Value>>();
+    if !unreproducible.is_empty() {
+        return Some(json!({ "Unreproducible": unreproducible }));
+    }
+
+    // Realised but then not checked, as r13y's second build failing
+    if results
+        .iter()
+        .any(|result| result.status == Status::InfraError && result.realise_duration.is_some())
+    {
+        return Some(json!("SecondFailed"));
+    }
+
+    None
+}
+
+/// Results as r13y build responses, one JSON object per line for each derivation with a result it
+/// has a status for, so they can be loaded into r13y's report generator and dashboards
+pub fn render(report: &Report) -> String {
+    let mut by_drv = BTreeMap::<&Path, Vec<&FodResult>>::new();
+    for result in &report.results {
+        by_drv.entry(&result.drv).or_default().push(result);
+    }
+
+    let mut lines = String::new();
+    for (drv, results) in by_drv {
+        let Some(status) = status(&results) else {
+            continue;
+        };
+
+        // Only the attr of the line's own derivation, as listing every attr on each line would
+        // grow with the square of the number of derivations
+        let request = json!({
+            "nixpkgs_revision": report.revision.as_deref().unwrap_or_default(),
+            "nixpkgs_sha256sum": "",
+            "result_url": "",
+            "subsets": [["Nixpkgs", [results[0].attr.split('.').collect::<Vec<_>>()]]],
+        });
+
+        lines.push_str(&format!(
+            "{}\n",
+            json!({
+                "V1": {
+                    "request": request,
+                    "drv": drv,
+                    "status": status,
+                }
+            })
+        ));
+    }
+
+    lines
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::drv::Derivation;
+use crate::git::git;
+
+pub static GITHUB_ARCHIVE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^https://(?:github\.com/([^/]+)/([^/]+)/archive/(.+)\.tar\.gz|codeload\.github\.com/([^/]+)/([^/]+)/tar\.gz/(.+))$").unwrap()
+});
+static GITLAB_ARCHIVE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^https://gitlab\.com/(.+?)/-/archive/([^/]+)/[^/]+\.tar\.gz$").unwrap()
+});
+
+/// Repository and rev a git-based FOD pins, to clone it from directly
+pub struct Source {
+    pub repo: String,
+    pub rev: String,
+    pub submodules: bool,
+    /// Whether the FOD is of a forge archive rather than a clone, which leaves out paths the
+    /// repository marks `export-ignore` and expands `export-subst`
+    pub archive: bool,
+}
+
+/// What cloning the pinned rev tells about a git-based FOD with a hash mismatch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The rev still has the expected content, so only the archive served for it changed
+    ArchiveChanged,
+    /// The rev has different content now, as when a tag is force-pushed
+    RevMoved,
+    /// The rev cannot be fetched from the repository any more
+    RevGone,
+}
+
+impl Verdict {
+    /// Stable name for the verdict in machine-readable output
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::ArchiveChanged => "archive-changed",
+            Verdict::RevMoved => "rev-moved",
+            Verdict::RevGone => "rev-gone",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Verdict::ArchiveChanged => "the pinned rev still has the expected content, only the archive served for it changed",
+            Verdict::RevMoved => "the pinned rev has different content now, it was likely force-pushed",
+            Verdict::RevGone => "the pinned rev is gone from the repository",
+        }
+    }
+}
+
+/// Whether fetchzip's unpacking script, which it passes to fetchurl as `postFetch`, only moves the
+/// archive's single top-level directory to the output, without `stripRoot = false` or a
+/// `postFetch` of the caller's own changing what ends up there
+fn plain_unpack(post_fetch: &str) -> bool {
+    post_fetch
+        .split_once(r#"mv "$unpackDir/$fn" "$out""#)
+        .and_then(|(_, rest)| rest.split_once(r#"chmod 755 "$out""#))
+        .is_some_and(|(extra, _)| extra.trim().is_empty())
+}
+
+/// Where to clone a FOD from, for fetchgit and the forge archives of fetchFromGitHub and
+/// fetchFromGitLab, leaving out clones keeping `.git` or only part of the tree, outputs hashed flat
+/// and ones changed by `postFetch`, as their hash is not of the tree alone
+pub fn source(derivation: &Derivation, urls: &[String]) -> Option<Source> {
+    let env = &derivation.env;
+    let set = |var: &str| env.get(var).is_some_and(|value| value == "1");
+    let empty = |var: &str| env.get(var).is_none_or(|value| value.trim().is_empty());
+
+    if !derivation
+        .outputs
+        .iter()
+        .all(|output| output.hash_algo.starts_with("r:"))
+        || !empty("extraPostFetch")
+    {
+        return None;
+    }
+
+    if let Some(rev) = env
+        .get("rev")
+        .filter(|_| env.contains_key("fetchSubmodules"))
+    {
+        if !empty("postFetch")
+            || set("leaveDotGit")
+            || set("deepClone")
+            || env
+                .get("sparseCheckout")
+                .is_some_and(|value| !value.trim().is_empty())
+        {
+            return None;
+        }
+
+        return Some(Source {
+            repo: env.get("url")?.clone(),
+            rev: rev.clone(),
+            submodules: set("fetchSubmodules"),
+            archive: false,
+        });
+    }
+
+    if !env
+        .get("postFetch")
+        .is_some_and(|post_fetch| plain_unpack(post_fetch))
+    {
+        return None;
+    }
+
+    let url = urls.first()?;
+    if let Some(captures) = GITHUB_ARCHIVE.captures(url) {
+        let group = |i: usize, j: usize| captures.get(i).or(captures.get(j)).map(|m| m.as_str());
+        return Some(Source {
+            repo: format!("https://github.com/{}/{}.git", group(1, 4)?, group(2, 5)?),
+            rev: group(3, 6)?.to_string(),
+            submodules: false,
+            archive: true,
+        });
+    }
+    if let Some(captures) = GITLAB_ARCHIVE.captures(url) {
+        return Some(Source {
+            repo: format!("https://gitlab.com/{}.git", &captures[1]),
+            rev: captures[2].to_string(),
+            submodules: false,
+            archive: true,
+        });
+    }
+
+    None
+}
+
+/// Removes the `.git` directories and files of the checkout and its submodules, as fetchgit does
+fn remove_git(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if entry.file_name() == ".git" {
+            if file_type.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        } else if file_type.is_dir() {
+            remove_git(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether any `.gitattributes` in the checkout marks paths `export-ignore` or `export-subst`, so
+/// forge archives of it differ from the tree without anything having changed
+pub fn export_attributes(dir: &Path) -> io::Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type()?.is_dir() {
+            if export_attributes(&path)? {
+                return Ok(true);
+            }
+        } else if entry.file_name() == ".gitattributes" {
+            let attributes = fs::read_to_string(&path)?;
+            if attributes.contains("export-ignore") || attributes.contains("export-subst") {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Whether git failed for the rev not being in the repository, rather than for reaching it
+fn missing_rev(err: &anyhow::Error) -> bool {
+    let message = format!("{:#}", err);
+
+    [
+        "couldn't find remote ref",
+        "not our ref",
+        "no such remote ref",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
+
+/// Checks out the pinned rev into `dir` with only its tree left, as nix-prefetch-git would,
+/// returning `RevGone` rather than failing when the repository no longer has it
+pub fn checkout(source: &Source, dir: &Path) -> Result<Option<Verdict>> {
+    git(dir, &["init", "--quiet"])?;
+
+    if let Err(err) = git(
+        dir,
+        &[
+            "fetch",
+            "--quiet",
+            "--depth",
+            "1",
+            &source.repo,
+            &source.rev,
+        ],
+    ) {
+        if missing_rev(&err) {
+            return Ok(Some(Verdict::RevGone));
+        }
+        return Err(err);
+    }
+    git(dir, &["checkout", "--quiet", "FETCH_HEAD"])?;
+    if source.submodules {
+        git(
+            dir,
+            &[
+                "submodule",
+                "update",
+                "--quiet",
+                "--init",
+                "--recursive",
+                "--depth",
+                "1",
+            ],
+        )?;
+    }
+
+    remove_git(dir)?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::drv::Output;
+
+    const URL: &str = "https://github.com/owner/repo/archive/v1.0.tar.gz";
+    const UNPACK: &str = r#"
+        unpackFile "$renamed"
+        fn=$(cd "$unpackDir" && ls -A)
+        mv "$unpackDir/$fn" "$out"
+
+        chmod 755 "$out"
+    "#;
+
+    fn derivation(hash_algo: &str, post_fetch: Option<&str>) -> Derivation {
+        Derivation {
+            outputs: vec![Output {
+                name: "out".to_string(),
+                path: "/nix/store/00000000000000000000000000000000-source".to_string(),
+                hash_algo: hash_algo.to_string(),
+                hash: String::new(),
+            }],
+            env: post_fetch
+                .map(|post_fetch| ("postFetch".to_string(), post_fetch.to_string()))
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn unpacked_github_archive() {
+        let source = source(&derivation("r:sha256", Some(UNPACK)), &[URL.to_string()]).unwrap();
+        assert_eq!(source.repo, "https://github.com/owner/repo.git");
+        assert_eq!(source.rev, "v1.0");
+        assert!(source.archive);
+    }
+
+    #[test]
+    fn flat_github_archive() {
+        // fetchurl of the tarball itself is hashed as the file, not the tree in it
+        assert!(source(&derivation("sha256", None), &[URL.to_string()]).is_none());
+        assert!(source(&derivation("sha256", Some(UNPACK)), &[URL.to_string()]).is_none());
+    }
+
+    #[test]
+    fn changed_unpack() {
+        let post_fetch = UNPACK.replace("\n\n", "\nrm -r \"$out/docs\"\n");
+        assert!(source(
+            &derivation("r:sha256", Some(&post_fetch)),
+            &[URL.to_string()]
+        )
+        .is_none());
+
+        let keep_root = UNPACK.replace(r#""$unpackDir/$fn""#, r#""$unpackDir""#);
+        assert!(source(
+            &derivation("r:sha256", Some(&keep_root)),
+            &[URL.to_string()]
+        )
+        .is_none());
+    }
+}
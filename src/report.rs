@@ -0,0 +1,1188 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::cache::NarInfo;
+use crate::content::Content;
+use crate::diagnose::Reachability;
+use crate::ecosystem::{Ecosystem, ECOSYSTEMS};
+use crate::export;
+use crate::hash::Hash;
+use crate::html;
+use crate::http;
+use crate::hydra::HydraBuild;
+use crate::log;
+use crate::markdown;
+use crate::options::Options;
+use crate::r13y;
+use crate::reclone::Verdict;
+use crate::state::State;
+use crate::swh::SwhStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    Reproducible,
+    NotReproducible,
+    Nondeterministic,
+    FetchFailed,
+    DeadUrl,
+    Unchecked,
+    TooLarge,
+    InfraError,
+}
+
+impl Status {
+    pub fn is_failure(self) -> bool {
+        !matches!(
+            self,
+            Status::Reproducible | Status::Unchecked | Status::TooLarge | Status::InfraError
+        )
+    }
+
+    /// Stable name for the status in machine-readable output
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Reproducible => "reproducible",
+            Status::NotReproducible => "not-reproducible",
+            Status::Nondeterministic => "nondeterministic",
+            Status::FetchFailed => "fetch-failed",
+            Status::DeadUrl => "dead-url",
+            Status::Unchecked => "unchecked",
+            Status::TooLarge => "too-large",
+            Status::InfraError => "infra-error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Status> {
+        [
+            Status::Reproducible,
+            Status::NotReproducible,
+            Status::Nondeterministic,
+            Status::FetchFailed,
+            Status::DeadUrl,
+            Status::Unchecked,
+            Status::TooLarge,
+            Status::InfraError,
+        ]
+        .into_iter()
+        .find(|status| status.name() == name)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Status::Reproducible => "reproducible",
+            Status::NotReproducible => "not reproducible",
+            Status::Nondeterministic => "different on each fetch",
+            Status::FetchFailed => "could not be fetched",
+            Status::DeadUrl => "dead upstream URLs",
+            Status::Unchecked => "not checked",
+            Status::TooLarge => "too large",
+            Status::InfraError => "infrastructure error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FodResult {
+    pub attr: String,
+    pub drv: PathBuf,
+    pub output: String,
+    pub out: PathBuf,
+    pub status: Status,
+    pub fetcher: &'static str,
+    pub expected_hash: String,
+    pub got_hash: Option<String>,
+    /// Hashes of the two fetches in a row that disagreed, for a nondeterministic FOD
+    pub double_fetched: Option<(String, String)>,
+    /// NAR hash of the output in the store, as realised from upstream
+    pub nar_hash: Option<String>,
+    pub cached: Option<NarInfo>,
+    pub hydra: Option<HydraBuild>,
+    pub urls: Vec<String>,
+    pub urls_alive: Option<bool>,
+    pub swh: Option<SwhStatus>,
+    pub wayback: Option<String>,
+    pub warnings: Vec<String>,
+    /// Tags assigned by hand in the history, on top of the automatic ones
+    pub tags: Vec<String>,
+    /// Reason from the ignore file for a failure already known about
+    pub known_issue: Option<String>,
+    /// Whether it recently went between passing and failing, so is reported apart until stable
+    pub quarantined: bool,
+    pub flaky: bool,
+    /// Nixpkgs revisions the derivation was found in, with `--revision`
+    pub revisions: Vec<String>,
+    /// Whether the derivation was only found with `--alias-pass`, through an alias
+    pub alias_only: bool,
+    /// What cloning the pinned rev of a git-based FOD with a hash mismatch found, with `--reclone`
+    pub reclone: Option<Verdict>,
+    /// How the files in an archive with a hash mismatch compare to those in the one served now
+    pub content: Option<Content>,
+    /// Ecosystem of a FOD vendoring language dependencies
+    pub ecosystem: Option<Ecosystem>,
+    /// Lockfile and tool version the vendored dependencies were resolved with
+    pub lockfile: Option<String>,
+    /// How far upstream could be reached after the fetch failed
+    pub reachability: Option<Reachability>,
+    /// Whether Nix fetched it in the build sandbox, as outside it checks can pass for impure
+    /// reasons
+    pub sandboxed: Option<bool>,
+    /// Where it stands over this run and the ones in the history before it
+    pub state: Option<State>,
+    /// Whether the fetch failed by timing out
+    pub timed_out: bool,
+    pub realise_duration: Option<Duration>,
+    pub check_duration: Option<Duration>,
+    pub nar_size: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttrTiming {
+    pub attr: String,
+    pub pname: Option<String>,
+    pub version: Option<String>,
+    pub instantiate: Duration,
+    pub requisites: Duration,
+    /// Whether instantiating it failed, so it had no FODs to check
+    pub failed: bool,
+}
+
+impl AttrTiming {
+    pub fn total(&self) -> Duration {
+        self.instantiate + self.requisites
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub results: Vec<FodResult>,
+    pub attrs: Vec<AttrTiming>,
+    /// Attrs and FODs left out before they had a result, by why
+    pub skipped: BTreeMap<&'static str, usize>,
+    /// Bytes Nix itself transferred, which leaves out what builders download
+    pub downloaded: u64,
+    /// Nixpkgs revision checked, when it is a Git checkout
+    pub revision: Option<String>,
+    /// Store URI every Nix command was given
+    pub store: Option<String>,
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match secs {
+        0..60 => format!("{:.1}s", duration.as_secs_f64()),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60),
+    }
+}
+
+impl FodResult {
+    pub fn duration(&self) -> Duration {
+        self.realise_duration.unwrap_or_default() + self.check_duration.unwrap_or_default()
+    }
+
+    /// Derivation path, qualified with the output name for additional outputs
+    pub fn target(&self) -> String {
+        if self.output == "out" {
+            self.drv.display().to_string()
+        } else {
+            format!("{}!{}", self.drv.display(), self.output)
+        }
+    }
+
+    /// Triage tags, automatic ones for what kind of failure it is first
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags = Vec::new();
+
+        if let Some(state) = self.state.filter(|state| *state != State::Passing) {
+            tags.push(state.name());
+        }
+        if self.status == Status::DeadUrl || self.urls_alive == Some(false) {
+            tags.push("dead-upstream");
+        }
+        match self.status {
+            Status::NotReproducible => tags.push("hash-mismatch"),
+            Status::Nondeterministic => tags.push("nondeterministic"),
+            Status::FetchFailed => tags.push("fetch-failed"),
+            _ => {}
+        }
+        if let Some(reachability) = self.reachability {
+            tags.push(reachability.tag());
+        }
+        if let Some(verdict) = self.reclone {
+            tags.push(verdict.name());
+        }
+        if let Some(content) = self.content {
+            tags.push(content.name());
+        }
+        if let Some(ecosystem) = self.ecosystem {
+            tags.push(ecosystem.name());
+        }
+        if self.sandboxed == Some(false) && self.realise_duration.is_some() {
+            tags.push("unsandboxed");
+        }
+        if self.flaky || self.status == Status::InfraError {
+            tags.push("transient");
+        }
+        if self.alias_only {
+            tags.push("alias-only");
+        }
+
+        tags.extend(self.tags.iter().map(String::as_str));
+        tags
+    }
+
+    /// Hash of the content upstream served, which is the expected one when the check passed
+    pub fn observed_hash(&self) -> Option<&str> {
+        match self.status {
+            Status::Reproducible => Some(&self.expected_hash),
+            _ => self.got_hash.as_deref(),
+        }
+    }
+
+    /// Result as uploaded with `--submit` and written with `--format json`, leaving out lookups
+    /// only meaningful locally
+    pub fn to_json(&self) -> Value {
+        json!({
+            "attr": self.attr,
+            "drv": self.drv,
+            "output": self.output,
+            "out": self.out,
+            "status": self.status.name(),
+            "fetcher": self.fetcher,
+            "expected_hash": self.expected_hash,
+            "got_hash": self.observed_hash(),
+            "double_fetched": self.double_fetched,
+            "nar_hash": self.nar_hash,
+            "urls": self.urls,
+            "tags": self.tags(),
+            "flaky": self.flaky,
+            "known_issue": self.known_issue,
+            "reachability": self.reachability.map(Reachability::name),
+            "sandboxed": self.sandboxed,
+            "state": self.state.map(State::name),
+            "reclone": self.reclone.map(Verdict::name),
+            "content": self.content.map(Content::name),
+            "ecosystem": self.ecosystem.map(Ecosystem::name),
+            "lockfile": self.lockfile,
+            "realise_duration": self.realise_duration.map(|duration| duration.as_secs_f64()),
+            "check_duration": self.check_duration.map(|duration| duration.as_secs_f64()),
+            "nar_size": self.nar_size,
+        })
+    }
+
+    pub fn host(&self) -> Option<String> {
+        self.urls.first().and_then(|url| http::host(url))
+    }
+
+    /// Package set an attr belongs to, keeping the target for cross-compiled ones
+    pub fn group(&self) -> String {
+        let mut components = self.attr.split('.');
+
+        match (components.next(), components.next(), components.next()) {
+            (Some("pkgsCross"), Some(target), Some(_)) => format!("pkgsCross.{}", target),
+            (Some(set), Some(_), _) => set.to_string(),
+            _ => "top-level".to_string(),
+        }
+    }
+}
+
+/// Results reported among the failures, leaving out known issues and quarantined results which are
+/// reported apart
+pub fn reported(results: &[FodResult]) -> Vec<&FodResult> {
+    results
+        .iter()
+        .filter(|result| result.known_issue.is_none() && !result.quarantined)
+        .collect()
+}
+
+/// Failure classes reports have a section for, in the order they come in
+pub const REASONS: &[&str] = &[
+    "Hash mismatch",
+    "Vendored dependencies",
+    "Unstable GitHub tarball",
+    "Recompressed",
+    "Nondeterministic",
+    "Dead URL",
+    "Timeout",
+    "Fetch failure",
+    "Infrastructure error",
+];
+
+/// Section of the report a result belongs in, with results that did not fail in none
+pub fn reason(result: &FodResult) -> Option<&'static str> {
+    match result.status {
+        // Vendored dependencies change with the tool resolving them far more often than upstream
+        Status::NotReproducible | Status::Nondeterministic if result.ecosystem.is_some() => {
+            Some("Vendored dependencies")
+        }
+        Status::NotReproducible if result.content == Some(Content::Regenerated) => {
+            Some("Unstable GitHub tarball")
+        }
+        Status::NotReproducible if result.content == Some(Content::Recompressed) => {
+            Some("Recompressed")
+        }
+        Status::NotReproducible => Some("Hash mismatch"),
+        Status::Nondeterministic => Some("Nondeterministic"),
+        Status::DeadUrl => Some("Dead URL"),
+        Status::FetchFailed if result.timed_out => Some("Timeout"),
+        Status::FetchFailed => Some("Fetch failure"),
+        Status::InfraError => Some("Infrastructure error"),
+        Status::Reproducible | Status::Unchecked | Status::TooLarge => None,
+    }
+}
+
+/// Results by the failure class they belong in, leaving out classes without any
+pub fn by_reason<'a>(results: &[&'a FodResult]) -> Vec<(&'static str, Vec<&'a FodResult>)> {
+    REASONS
+        .iter()
+        .map(|&class| {
+            let members = results
+                .iter()
+                .copied()
+                .filter(|result| reason(result) == Some(class))
+                .collect::<Vec<&FodResult>>();
+            (class, members)
+        })
+        .filter(|(_, members)| !members.is_empty())
+        .collect()
+}
+
+/// Attrs that failed to evaluate, which have no FOD results of their own
+pub fn eval_failures(report: &Report) -> Vec<&str> {
+    let mut failed = report
+        .attrs
+        .iter()
+        .filter(|timing| timing.failed)
+        .map(|timing| timing.attr.as_str())
+        .collect::<Vec<&str>>();
+    failed.sort();
+    failed
+}
+
+/// Failures fetching the same URLs for the same hash, which are the same failure however many
+/// attrs use them, with the others after the first
+pub fn collapse<'a>(results: &[&'a FodResult]) -> Vec<(&'a FodResult, Vec<&'a FodResult>)> {
+    let mut collapsed = Vec::<(&FodResult, Vec<&FodResult>)>::new();
+    let mut seen = HashMap::<(&[String], &str, Status), usize>::new();
+
+    for &result in results {
+        if !result.status.is_failure() || result.urls.is_empty() {
+            collapsed.push((result, vec![]));
+            continue;
+        }
+
+        let key = (
+            &result.urls[..],
+            result.expected_hash.as_str(),
+            result.status,
+        );
+        match seen.get(&key) {
+            Some(&i) => collapsed[i].1.push(result),
+            None => {
+                seen.insert(key, collapsed.len());
+                collapsed.push((result, vec![]));
+            }
+        }
+    }
+
+    collapsed
+}
+
+pub fn also_affects(also: &[&FodResult]) -> String {
+    also.iter()
+        .map(|result| format!("{} at {}", result.attr, result.target()))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Results by the package set their attr belongs to
+pub fn groups(results: &[FodResult]) -> BTreeMap<String, Vec<&FodResult>> {
+    let mut groups = BTreeMap::<String, Vec<&FodResult>>::new();
+
+    for result in results {
+        groups.entry(result.group()).or_default().push(result);
+    }
+
+    groups
+}
+
+/// Hosts where at least `threshold` FODs failed and few or none succeeded
+fn outages(results: &[FodResult], threshold: usize) -> BTreeMap<String, Vec<&FodResult>> {
+    let mut hosts = BTreeMap::<String, (Vec<&FodResult>, usize)>::new();
+
+    for result in results {
+        if let Some(host) = result.host() {
+            let (failed, succeeded) = hosts.entry(host).or_default();
+            if result.status.is_failure() {
+                failed.push(result);
+            } else {
+                *succeeded += 1;
+            }
+        }
+    }
+
+    hosts
+        .into_iter()
+        .filter(|(_, (failed, succeeded))| {
+            threshold > 0 && failed.len() >= threshold && *succeeded * 4 <= failed.len()
+        })
+        .map(|(host, (failed, _))| (host, failed))
+        .collect()
+}
+
+/// What Hydra's build of a failed FOD tells, comparing the output it has with the one fetched here
+fn hydra_note(opts: &Options, result: &FodResult) -> String {
+    let Some(hydra_url) = &opts.hydra else {
+        return String::new();
+    };
+    let Some(build) = &result.hydra else {
+        return " (not found on Hydra)".to_string();
+    };
+
+    let link = format!("{}/build/{}", hydra_url.trim_end_matches('/'), build.id);
+    let same = match (&build.nar_hash, &result.nar_hash) {
+        (Some(hydra), Some(local)) => Some(
+            Hash::parse(hydra, None)
+                .zip(Hash::parse(local, None))
+                .map_or(hydra == local, |(hydra, local)| hydra == local),
+        ),
+        _ => None,
+    };
+
+    match (result.status, same) {
+        (_, Some(true)) => format!(" (Hydra has the same content from {})", link),
+        (Status::NotReproducible | Status::Nondeterministic, Some(false)) => format!(
+            " (upstream changed after Hydra fetched it in {}, with NAR hash {})",
+            link,
+            build.nar_hash.as_deref().unwrap_or_default()
+        ),
+        (Status::FetchFailed, _) => {
+            format!(" (Hydra fetched it in {} while upstream served it)", link)
+        }
+        _ => format!(" (Hydra fetched it in {})", link),
+    }
+}
+
+pub fn print(opts: &Options, report: &mut Report) {
+    let results = &mut report.results;
+    results.sort_by(|a, b| (&a.attr, &a.drv, &a.output).cmp(&(&b.attr, &b.drv, &b.output)));
+
+    let rendered = match opts.format.as_str() {
+        "markdown" => Some(markdown::render(report)),
+        "html" => Some(html::render(report)),
+        "r13y" => Some(r13y::render(report)),
+        "json" => Some(export::json(report)),
+        "csv" => Some(export::csv(report)),
+        _ => None,
+    };
+    let results = &report.results;
+
+    if let Some(rendered) = rendered {
+        match &opts.output {
+            Some(output) => {
+                if let Err(err) = fs::write(output, rendered) {
+                    error!("Error writing report to {}: {}", output.display(), err);
+                }
+            }
+            None => print!("{}", rendered),
+        }
+        return;
+    }
+
+    if opts.substitute {
+        info!("Note: FOD outputs may have been substituted from a binary cache, so only the check fetched from upstream");
+    } else {
+        info!("Note: FOD outputs were realised with substitution disabled, so each was fetched from upstream before being checked");
+    }
+
+    let outages = outages(results, opts.outage_threshold);
+
+    for (host, failed) in &outages {
+        println!(
+            "{}",
+            log::red(&format!(
+                "Host {} appears down, {} FODs from it failed:",
+                host,
+                failed.len()
+            ))
+        );
+        for result in failed {
+            println!("  {} at {}", result.attr, result.target());
+        }
+    }
+
+    let reported = results
+        .iter()
+        .filter(|result| {
+            result.known_issue.is_none()
+                && !result.quarantined
+                && !result
+                    .host()
+                    .is_some_and(|host| outages.contains_key(&host))
+        })
+        .collect::<Vec<&FodResult>>();
+
+    for result in reported
+        .iter()
+        .filter(|result| result.status == Status::TooLarge)
+    {
+        println!(
+            "FOD from {} at {} was skipped for being too large",
+            result.attr,
+            result.target()
+        );
+    }
+
+    for (class, members) in by_reason(&reported) {
+        println!("{}", log::bold(&format!("{} ({}):", class, members.len())));
+        print_failures(opts, &members);
+    }
+
+    let eval_failures = eval_failures(report);
+    if !eval_failures.is_empty() {
+        println!(
+            "{}",
+            log::bold(&format!("Eval failure ({}):", eval_failures.len()))
+        );
+        for attr in eval_failures {
+            println!("{}", log::red(&format!("{} failed to evaluate", attr)));
+        }
+    }
+
+    for result in results.iter().filter(|result| result.flaky) {
+        println!(
+            "{}",
+            log::yellow(&format!(
+                "FOD from {} at {} failed at first but passed on a re-check",
+                result.attr,
+                result.target()
+            ))
+        );
+    }
+
+    if opts.preflight {
+        for result in results.iter() {
+            if result.urls_alive == Some(false) && result.status != Status::DeadUrl {
+                println!(
+                    "{}",
+                    log::red(&format!(
+                        "FOD from {} at {} has only dead upstream URLs{}",
+                        result.attr,
+                        result.target(),
+                        result
+                            .wayback
+                            .as_ref()
+                            .map(|snapshot| format!(", archived at {}", snapshot))
+                            .unwrap_or_default()
+                    ))
+                );
+            }
+        }
+    }
+
+    if results.iter().any(|result| !result.warnings.is_empty()) {
+        println!("{}", log::bold("Warnings:"));
+        for result in results.iter() {
+            for warning in &result.warnings {
+                println!(
+                    "  {}",
+                    log::yellow(&format!(
+                        "{} at {}: {}",
+                        result.attr,
+                        result.target(),
+                        warning
+                    ))
+                );
+            }
+        }
+    }
+
+    if opts.swh {
+        for result in results.iter() {
+            let Some(swh) = &result.swh else {
+                continue;
+            };
+
+            println!(
+                "Software Heritage {} source for {} at {}{}",
+                if swh.archived {
+                    "has archived"
+                } else {
+                    "has not archived"
+                },
+                result.attr,
+                result.target(),
+                swh.save_request
+                    .as_ref()
+                    .map(|status| format!(", save request {}", status))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    if let Some(cache_url) = &opts.binary_cache_check {
+        for result in results.iter() {
+            let Some(cached) = &result.cached else {
+                continue;
+            };
+
+            // Only fetching upstream again tells anything about what the cache still has
+            match result.status {
+                Status::NotReproducible => println!(
+                    "Binary cache {} has {} from {} with NAR hash {}, where upstream now serves {}",
+                    cache_url,
+                    result.out.display(),
+                    result.attr,
+                    cached.nar_hash,
+                    result.got_hash.as_deref().unwrap_or("content of another hash")
+                ),
+                Status::Nondeterministic => println!(
+                    "Binary cache {} has {} from {} with NAR hash {}, where upstream serves different content on each fetch",
+                    cache_url,
+                    result.out.display(),
+                    result.attr,
+                    cached.nar_hash
+                ),
+                Status::FetchFailed => println!(
+                    "Binary cache {} still has {} from {} with NAR hash {}, where upstream is unreachable",
+                    cache_url,
+                    result.out.display(),
+                    result.attr,
+                    cached.nar_hash
+                ),
+                Status::Reproducible => {
+                    if let Some(nar_hash) = &result.nar_hash {
+                        if *nar_hash != cached.nar_hash {
+                            println!(
+                                "Binary cache {} has NAR hash {} for {} from {} but upstream content has {}",
+                                cache_url,
+                                cached.nar_hash,
+                                result.out.display(),
+                                result.attr,
+                                nar_hash
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    print_coverage(report);
+    print_known_issues(results);
+    print_quarantined(results);
+    print_alias_only(results);
+    print_tags(results);
+    print_revisions(results);
+
+    if !log::quiet() {
+        print_budget(results);
+        print_eval_hotspots(&mut report.attrs);
+    }
+
+    print_summary(results);
+    if let Some(bandwidth) = bandwidth(report) {
+        println!("{} {}", log::bold("Bandwidth:"), bandwidth);
+    }
+}
+
+/// Bandwidth a run used, from what Nix transferred and the size of the FOD outputs checked, as
+/// builders fetching with curl and the like do not tell Nix what they download
+pub fn bandwidth(report: &Report) -> Option<String> {
+    let checked = report
+        .results
+        .iter()
+        .filter(|result| result.check_duration.is_some())
+        .filter_map(|result| result.nar_size)
+        .sum::<u64>();
+
+    if report.downloaded == 0 && checked == 0 {
+        return None;
+    }
+
+    Some(format!(
+        "{} transferred by Nix, and FOD outputs totalling {} fetched from upstream at least once",
+        format_size(report.downloaded),
+        format_size(checked)
+    ))
+}
+
+/// What a run left out and why, to tell how much of Nixpkgs it covered
+pub fn coverage(report: &Report) -> Vec<(&'static str, usize)> {
+    let count = |matches: &dyn Fn(&FodResult) -> bool| {
+        report
+            .results
+            .iter()
+            .filter(|result| matches(result))
+            .count()
+    };
+
+    let mut coverage = report
+        .skipped
+        .iter()
+        .map(|(reason, count)| (*reason, *count))
+        .collect::<Vec<(&str, usize)>>();
+    coverage.extend([
+        (
+            "FOD outputs over the size limit",
+            count(&|result| result.status == Status::TooLarge),
+        ),
+        (
+            "FOD outputs skipped for dead URLs",
+            count(&|result| result.status == Status::DeadUrl && result.realise_duration.is_none()),
+        ),
+        (
+            "FOD outputs only linted",
+            count(&|result| result.status == Status::Unchecked),
+        ),
+        (
+            "FOD outputs stopped by infrastructure errors",
+            count(&|result| result.status == Status::InfraError),
+        ),
+        (
+            "FOD outputs checked but quarantined",
+            count(&|result| result.quarantined),
+        ),
+    ]);
+
+    coverage.retain(|(_, count)| *count > 0);
+    coverage
+}
+
+/// Whether a FOD output was never fetched from upstream
+fn left_out(result: &FodResult) -> bool {
+    matches!(
+        result.status,
+        Status::TooLarge | Status::Unchecked | Status::InfraError
+    ) || (result.status == Status::DeadUrl && result.realise_duration.is_none())
+}
+
+/// FOD outputs the run got a result from upstream for
+pub fn checked(results: &[FodResult]) -> usize {
+    results.iter().filter(|result| !left_out(result)).count()
+}
+
+/// Prints a line for each of a section's failures, with those sharing a cause collapsed into one
+fn print_failures(opts: &Options, results: &[&FodResult]) {
+    for (result, also) in collapse(results) {
+        match result.status {
+            Status::Reproducible => {}
+            Status::NotReproducible => println!(
+                "{}",
+                log::red(&format!(
+                    "FOD from {} at {} is not reproducible{}{}{}{}",
+                    result.attr,
+                    result.target(),
+                    result
+                        .got_hash
+                        .as_ref()
+                        .map(|got| format!(", expected {} but got {}", result.expected_hash, got))
+                        .unwrap_or_default(),
+                    result
+                        .content
+                        .map(|content| format!(", {}", content.description()))
+                        .unwrap_or_default(),
+                    result
+                        .reclone
+                        .map(|verdict| format!(", {}", verdict.description()))
+                        .unwrap_or_default(),
+                    hydra_note(opts, result)
+                ))
+            ),
+            Status::Nondeterministic => println!(
+                "{}",
+                log::red(&format!(
+                    "FOD from {} at {} served different content on two fetches in a row",
+                    result.attr,
+                    result.target()
+                ))
+            ),
+            Status::FetchFailed => println!(
+                "{}",
+                log::red(&format!(
+                    "FOD from {} at {} {}{}{}",
+                    result.attr,
+                    result.target(),
+                    if result.timed_out {
+                        "timed out fetching"
+                    } else {
+                        "could not be fetched"
+                    },
+                    result
+                        .reachability
+                        .map(|reachability| format!(", {}", reachability.description()))
+                        .unwrap_or_default(),
+                    hydra_note(opts, result)
+                ))
+            ),
+            Status::InfraError => println!(
+                "{}",
+                log::red(&format!(
+                    "FOD from {} at {} could not be checked due to an infrastructure error",
+                    result.attr,
+                    result.target()
+                ))
+            ),
+            Status::DeadUrl => println!(
+                "{}",
+                log::red(&format!(
+                    "FOD from {} at {} has only dead upstream URLs, skipped{}",
+                    result.attr,
+                    result.target(),
+                    result
+                        .wayback
+                        .as_ref()
+                        .map(|snapshot| format!(", archived at {}", snapshot))
+                        .unwrap_or_default()
+                ))
+            ),
+            Status::TooLarge | Status::Unchecked => {}
+        }
+
+        if let Some(lockfile) = &result.lockfile {
+            println!("  Vendored with {}", lockfile);
+        }
+        if !also.is_empty() {
+            println!("  Also affects {}", also_affects(&also));
+        }
+    }
+}
+
+fn print_coverage(report: &Report) {
+    let coverage = coverage(report);
+    if coverage.is_empty() {
+        return;
+    }
+
+    println!(
+        "{} {} of {} FOD outputs checked, left out:",
+        log::bold("Coverage:"),
+        checked(&report.results),
+        report.results.len()
+    );
+    for (reason, count) in coverage {
+        println!("  {}: {}", reason, count);
+    }
+}
+
+fn print_known_issues(results: &[FodResult]) {
+    let known = known_issues(results);
+    if known.is_empty() {
+        return;
+    }
+
+    println!("{}", log::bold("Known issues:"));
+    for result in known {
+        println!(
+            "  FOD from {} at {} {}: {}",
+            result.attr,
+            result.target(),
+            result.status.description(),
+            result.known_issue.as_deref().unwrap_or_default()
+        );
+    }
+}
+
+fn print_alias_only(results: &[FodResult]) {
+    let alias_only = alias_only(results);
+    if alias_only.is_empty() {
+        return;
+    }
+
+    println!(
+        "{}",
+        log::bold(&format!(
+            "Only reachable through aliases ({}):",
+            alias_only.len()
+        ))
+    );
+    for result in alias_only {
+        println!(
+            "  FOD from {} at {} {}",
+            result.attr,
+            result.target(),
+            result.status.description()
+        );
+    }
+}
+
+fn print_quarantined(results: &[FodResult]) {
+    let quarantined = quarantined(results);
+    if quarantined.is_empty() {
+        return;
+    }
+
+    println!("{}", log::bold("Quarantined as flaky:"));
+    for result in quarantined {
+        println!(
+            "  FOD from {} at {} {}{}",
+            result.attr,
+            result.target(),
+            result.status.description(),
+            if result.flaky { ", after a retry" } else { "" }
+        );
+    }
+}
+
+/// Results of attrs in quarantine, which are reported apart from the others
+pub fn quarantined(results: &[FodResult]) -> Vec<&FodResult> {
+    results
+        .iter()
+        .filter(|result| result.quarantined && result.known_issue.is_none())
+        .collect()
+}
+
+/// Results of derivations only reachable through aliases, found by `--alias-pass`
+pub fn alias_only(results: &[FodResult]) -> Vec<&FodResult> {
+    results.iter().filter(|result| result.alias_only).collect()
+}
+
+/// Failures matching the ignore file, which are reported apart from the others
+pub fn known_issues(results: &[FodResult]) -> Vec<&FodResult> {
+    results
+        .iter()
+        .filter(|result| result.known_issue.is_some())
+        .collect()
+}
+
+/// Failing and flaky FODs by tag, so e.g. hash mismatches needing review stand apart from flakes
+fn print_tags(results: &[FodResult]) {
+    let mut by_tag = BTreeMap::<&str, Vec<&FodResult>>::new();
+    for result in results {
+        for tag in result.tags() {
+            by_tag.entry(tag).or_default().push(result);
+        }
+    }
+
+    if by_tag.is_empty() {
+        return;
+    }
+
+    println!("{}", log::bold("Tags:"));
+    for (tag, tagged) in by_tag {
+        println!("  {} ({}):", tag, tagged.len());
+        for result in tagged {
+            println!("    {} at {}", result.attr, result.target());
+        }
+    }
+}
+
+fn print_eval_hotspots(attrs: &mut [AttrTiming]) {
+    if attrs.is_empty() {
+        return;
+    }
+
+    let total: Duration = attrs.iter().map(AttrTiming::total).sum();
+    println!(
+        "Evaluated {} attrs in {} of evaluation time",
+        attrs.len(),
+        format_duration(total)
+    );
+
+    attrs.sort_by_key(|timing| std::cmp::Reverse(timing.total()));
+    println!("Slowest attrs to evaluate:");
+    for timing in attrs.iter().take(10) {
+        let package = match (&timing.pname, &timing.version) {
+            (Some(pname), Some(version)) => format!(" ({} {})", pname, version),
+            (Some(pname), None) => format!(" ({})", pname),
+            _ => String::new(),
+        };
+        println!(
+            "  {}{}: {} instantiating, {} scanning requisites",
+            timing.attr,
+            package,
+            format_duration(timing.instantiate),
+            format_duration(timing.requisites)
+        );
+    }
+}
+
+/// Results by the Nixpkgs revision they were found in, sharing those found in several
+pub fn by_revision(results: &[FodResult]) -> BTreeMap<&str, Vec<&FodResult>> {
+    let mut by_revision = BTreeMap::<&str, Vec<&FodResult>>::new();
+    for result in results {
+        for revision in &result.revisions {
+            by_revision.entry(revision).or_default().push(result);
+        }
+    }
+
+    by_revision
+}
+
+fn print_revisions(results: &[FodResult]) {
+    let by_revision = by_revision(results);
+    if by_revision.is_empty() {
+        return;
+    }
+
+    println!("{}", log::bold("Per revision:"));
+    for (revision, results) in by_revision {
+        println!("  {}: {}", revision, summary(results));
+    }
+}
+
+fn print_summary(results: &[FodResult]) {
+    println!("{} {}", log::bold("Summary:"), summary(results));
+    if let Some(states) = states(results) {
+        println!("{} {}", log::bold("States:"), states);
+    }
+    if let Some(ecosystems) = ecosystems(results) {
+        println!("{} {}", log::bold("Ecosystems:"), ecosystems);
+    }
+}
+
+/// Reproducibility rate of each ecosystem vendoring language dependencies, over the outputs
+/// checked, when any were
+pub fn ecosystems(results: &[FodResult]) -> Option<String> {
+    let rates = ECOSYSTEMS
+        .iter()
+        .filter_map(|&ecosystem| {
+            let checked = results
+                .iter()
+                .filter(|result| result.ecosystem == Some(ecosystem) && !left_out(result))
+                .collect::<Vec<&FodResult>>();
+            if checked.is_empty() {
+                return None;
+            }
+
+            let reproducible = checked
+                .iter()
+                .filter(|result| result.status == Status::Reproducible)
+                .count();
+            Some(format!(
+                "{} {} of {} reproducible ({:.0}%)",
+                ecosystem.name(),
+                reproducible,
+                checked.len(),
+                reproducible as f64 * 100.0 / checked.len() as f64
+            ))
+        })
+        .collect::<Vec<String>>();
+
+    (!rates.is_empty()).then(|| rates.join(", "))
+}
+
+/// Counts of the results that are not simply passing by their state over runs, when any are not
+pub fn states(results: &[FodResult]) -> Option<String> {
+    let count = |state: State| {
+        results
+            .iter()
+            .filter(|result| result.state == Some(state))
+            .count()
+    };
+    let (broken, suspect, fixed) = (
+        count(State::ConfirmedBroken),
+        count(State::Suspect),
+        count(State::Fixed),
+    );
+    if broken + suspect + fixed == 0 {
+        return None;
+    }
+
+    Some(format!(
+        "{} {}, {} {}, {} {}",
+        broken,
+        State::ConfirmedBroken.description(),
+        suspect,
+        State::Suspect.description(),
+        fixed,
+        State::Fixed.description()
+    ))
+}
+
+pub fn summary<'a>(results: impl IntoIterator<Item = &'a FodResult>) -> String {
+    let mut counts = HashMap::<Status, usize>::new();
+    let mut total = 0;
+    for result in results {
+        *counts.entry(result.status).or_default() += 1;
+        total += 1;
+    }
+    let count = |status: Status| counts.get(&status).copied().unwrap_or_default();
+
+    format!(
+        "{} FOD outputs, {} reproducible, {} not reproducible, {} nondeterministic, {} could not be fetched, {} dead, {} skipped, {} infrastructure errors",
+        total,
+        count(Status::Reproducible),
+        count(Status::NotReproducible),
+        count(Status::Nondeterministic),
+        count(Status::FetchFailed),
+        count(Status::DeadUrl),
+        count(Status::TooLarge) + count(Status::Unchecked),
+        count(Status::InfraError)
+    )
+}
+
+fn print_budget(results: &[FodResult]) {
+    let checked = results
+        .iter()
+        .filter(|result| result.realise_duration.is_some())
+        .collect::<Vec<&FodResult>>();
+
+    if checked.is_empty() {
+        return;
+    }
+
+    // Each FOD is downloaded once when realised and once more when checked
+    let downloaded: u64 = checked
+        .iter()
+        .filter_map(|result| result.nar_size)
+        .sum::<u64>()
+        * 2;
+    let duration: Duration = checked.iter().map(|result| result.duration()).sum();
+
+    println!(
+        "Checked {} FOD outputs, downloading about {} in {} of fetch time",
+        checked.len(),
+        format_size(downloaded),
+        format_duration(duration)
+    );
+
+    let mut slowest = checked.clone();
+    slowest.sort_by_key(|result| std::cmp::Reverse(result.duration()));
+    println!("Slowest FODs:");
+    for result in slowest.iter().take(10) {
+        println!(
+            "  {} at {}: {}",
+            result.attr,
+            result.target(),
+            format_duration(result.duration())
+        );
+    }
+
+    let mut largest = checked;
+    largest.sort_by_key(|result| std::cmp::Reverse(result.nar_size));
+    println!("Largest FODs:");
+    for result in largest.iter().take(10) {
+        if let Some(size) = result.nar_size {
+            println!(
+                "  {} at {}: {}",
+                result.attr,
+                result.target(),
+                format_size(size)
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::{Context, Result};
+use tempfile::{tempdir, TempDir};
+
+extern "C" {
+    fn kill(pid: i32, sig: i32) -> i32;
+    fn geteuid() -> u32;
+}
+
+/// Directory holding this run's GC roots, removed again once the run is done
+pub enum Roots {
+    Temporary(TempDir),
+    Persistent(PathBuf),
+}
+
+fn alive(pid: i32) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists and may be signalled
+    let signalled = unsafe { kill(pid, 0) } == 0;
+
+    // EPERM means it exists, but belongs to someone else
+    signalled || io::Error::last_os_error().raw_os_error() == Some(1)
+}
+
+/// Creates `dir` and its parents owner-only, refusing directories others could plant roots in
+fn create_private(dir: &Path) -> Result<()> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .context(format!("Creating roots directory {}", dir.display()))?;
+
+    let metadata =
+        fs::symlink_metadata(dir).context(format!("Reading roots directory {}", dir.display()))?;
+    // SAFETY: geteuid cannot fail
+    let uid = unsafe { geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o022 != 0 {
+        bail!(
+            "Roots directory {} must be a directory owned by and only writable by the current user",
+            dir.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the roots of runs that exited without cleaning up after themselves
+fn clean_stale(dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(dir).context(format!("Listing roots directory {}", dir.display()))? {
+        let entry = entry.context("Reading roots directory entry")?;
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<i32>().ok())
+        else {
+            continue;
+        };
+
+        if !alive(pid) {
+            info!(
+                "Removing stale GC roots from crashed run {} in {}",
+                pid,
+                dir.display()
+            );
+            fs::remove_dir_all(entry.path()).context(format!(
+                "Removing stale roots in {}",
+                entry.path().display()
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Roots {
+    /// Roots in `dir` when given, so they outlive a crash and are cleaned up by the next run,
+    /// otherwise in a temporary directory
+    pub fn new(dir: Option<&Path>) -> Result<Roots> {
+        let Some(dir) = dir else {
+            return Ok(Roots::Temporary(
+                tempdir().context("Creating roots directory")?,
+            ));
+        };
+
+        create_private(dir)?;
+        clean_stale(dir)?;
+
+        let path = dir.join(process::id().to_string());
+        for subdir in ["attrs", "drvs"] {
+            create_private(&path.join(subdir))?;
+        }
+
+        Ok(Roots::Persistent(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Roots::Temporary(dir) => dir.path(),
+            Roots::Persistent(path) => path,
+        }
+    }
+}
+
+impl Drop for Roots {
+    fn drop(&mut self) {
+        if let Roots::Persistent(path) = self {
+            if let Err(err) = fs::remove_dir_all(&*path) {
+                warn!(
+                    "Error removing GC roots in {}, ignoring: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// File name for an attr's root, escaped so nested and quoted attr names cannot collide
+pub fn attr_name(attr: &str) -> String {
+    attr.replace('%', "%25").replace('/', "%2F")
+}
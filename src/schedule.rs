@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::cache;
+use crate::drv::Derivation;
+
+fn expected_size(
+    drv: &Path,
+    sizes: &HashMap<PathBuf, u64>,
+    cache_url: Option<&str>,
+) -> Option<u64> {
+    let derivation = Derivation::read(drv).ok()?;
+
+    if !derivation.is_fixed_output() {
+        return Some(0);
+    }
+
+    derivation
+        .outputs
+        .iter()
+        .map(|output| output_size(Path::new(&output.path), sizes, cache_url))
+        .sum()
+}
+
+/// Known download size of an output, from previous runs or a binary cache
+pub fn output_size(
+    out: &Path,
+    sizes: &HashMap<PathBuf, u64>,
+    cache_url: Option<&str>,
+) -> Option<u64> {
+    sizes.get(out).copied().or_else(|| {
+        cache::narinfo(cache_url?, out)
+            .ok()
+            .flatten()
+            .and_then(|narinfo| narinfo.nar_size)
+    })
+}
+
+/// Orders derivations so the smallest fetches go first and unknown sizes sit in the middle
+pub fn by_size(
+    queue: &mut [(PathBuf, String)],
+    sizes: &HashMap<PathBuf, u64>,
+    cache_url: Option<&str>,
+) {
+    let expected = queue
+        .par_iter()
+        .map(|(drv, _)| (drv.clone(), expected_size(drv, sizes, cache_url)))
+        .collect::<HashMap<PathBuf, Option<u64>>>();
+
+    // Derivations that are not FODs count as zero-sized and would drag the median down
+    let mut known = expected
+        .values()
+        .flatten()
+        .copied()
+        .filter(|&size| size > 0)
+        .collect::<Vec<u64>>();
+    known.sort_unstable();
+    let median = known.get(known.len() / 2).copied().unwrap_or(0);
+
+    queue.sort_by_key(|(drv, _)| expected[drv].unwrap_or(median));
+}
+
+/// Deterministic order to check derivations in, by attr and then derivation path
+pub fn sort(queue: &mut [(PathBuf, String)]) {
+    queue.sort_by(|(a_drv, a_attr), (b_drv, b_attr)| (a_attr, a_drv).cmp(&(b_attr, b_drv)));
+}
+
+/// SplitMix64, which is plenty to spread checks around and stays the same across releases
+fn next(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Shuffles the queue, the same way for the same seed
+pub fn shuffle(queue: &mut [(PathBuf, String)], seed: u64) {
+    let mut state = seed;
+
+    for i in (1..queue.len()).rev() {
+        let j = (next(&mut state) % (i as u64 + 1)) as usize;
+        queue.swap(i, j);
+    }
+}
+
+/// Drops the derivations up to and including the attr or derivation `point`, returning whether
+/// it was in the queue
+pub fn start_after(queue: &mut Vec<(PathBuf, String)>, point: &str) -> bool {
+    // After an attr means after the last of its derivations
+    let end = match queue.iter().rposition(|(_, attr)| attr == point) {
+        Some(last) => last + 1,
+        None => match queue.iter().position(|(drv, _)| drv == Path::new(point)) {
+            Some(position) => position + 1,
+            None => return false,
+        },
+    };
+
+    queue.drain(..end);
+    true
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+pub struct Request {
+    pub method: String,
+    /// Percent-decoded path, without the query string
+    pub path: String,
+    /// Percent-decoded query parameters, the last one winning for repeated names
+    pub query: BTreeMap<String, String>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &serde_json::Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub fn not_found() -> Response {
+        Response::json(404, &serde_json::json!({ "error": "not found" }))
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(c) => {
+                decoded.push(c);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (
+                decode(&name.replace('+', " ")),
+                decode(&value.replace('+', " ")),
+            )
+        })
+        .collect()
+}
+
+fn respond(mut stream: TcpStream, handler: &impl Fn(&Request) -> Response) -> Result<()> {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .context("Setting read timeout")?;
+
+    let mut reader = BufReader::new(stream.try_clone().context("Cloning connection")?);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .context("Reading request")?;
+
+    // Headers are not needed for anything, but have to be read past
+    let mut header = String::new();
+    while reader.read_line(&mut header).context("Reading headers")? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => {
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let request = Request {
+                method: method.to_string(),
+                path: decode(path),
+                query: parse_query(query),
+            };
+
+            if request.method == "GET" || request.method == "HEAD" {
+                handler(&request)
+            } else {
+                Response::json(405, &serde_json::json!({ "error": "method not allowed" }))
+            }
+        }
+        _ => Response::json(400, &serde_json::json!({ "error": "bad request" })),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    )
+    .context("Writing response headers")?;
+    if !request_line.starts_with("HEAD ") {
+        stream
+            .write_all(&response.body)
+            .context("Writing response body")?;
+    }
+
+    Ok(())
+}
+
+/// Serves requests on `addr` with `handler`, answering each in its own thread
+pub fn serve(addr: &str, handler: impl Fn(&Request) -> Response + Sync) -> Result<()> {
+    let listener = TcpListener::bind(addr).context(format!("Listening on {}", addr))?;
+    info!("Listening on http://{}", addr);
+
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let handler = &handler;
+                    scope.spawn(move || {
+                        if let Err(err) = respond(stream, handler) {
+                            warn!("Error answering HTTP request, ignoring: {:#}", err);
+                        }
+                    });
+                }
+                Err(err) => warn!("Error accepting HTTP connection, ignoring: {}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+# Top-level attrs of Nixpkgs split up for enumerating: the derivations themselves, and the package
+# sets nix-env would recurse into, so each set can be enumerated on its own
+let
+  pkgs = import ./. { };
+
+  # Attrs failing to evaluate are left out, as nix-env leaves them out too
+  try = expr: let result = builtins.tryEval expr; in result.success && result.value;
+
+  isDerivation = name: try (pkgs.${name}.type or null == "derivation");
+  isSet = name: !isDerivation name && try (pkgs.${name}.recurseForDerivations or false);
+
+  # Strings only, as nix-env --json gives them
+  field = name: field:
+    let result = builtins.tryEval (pkgs.${name}.${field} or null);
+    in if result.success && builtins.isString result.value then result.value else null;
+
+  names = builtins.attrNames pkgs;
+in
+{
+  # In the shape of nix-env --query --json output
+  derivations = builtins.listToAttrs (map (name: {
+    inherit name;
+    value = { pname = field name "pname"; version = field name "version"; };
+  }) (builtins.filter isDerivation names));
+  sets = builtins.filter isSet names;
+}
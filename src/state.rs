@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use crate::history::History;
+use crate::report::{FodResult, Status};
+
+/// Where a FOD output stands over consecutive runs, so one failing run reads as a suspicion and
+/// only failures that last read as broken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
+    Passing,
+    /// Failed in fewer consecutive runs than it takes to be confirmed broken
+    Suspect,
+    ConfirmedBroken,
+    /// Passed in the first run after being confirmed broken
+    Fixed,
+}
+
+impl State {
+    /// Stable name for the state in machine-readable output
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Passing => "passing",
+            State::Suspect => "suspect",
+            State::ConfirmedBroken => "confirmed-broken",
+            State::Fixed => "fixed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<State> {
+        [
+            State::Passing,
+            State::Suspect,
+            State::ConfirmedBroken,
+            State::Fixed,
+        ]
+        .into_iter()
+        .find(|state| state.name() == name)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            State::Passing => "passing",
+            State::Suspect => "suspect",
+            State::ConfirmedBroken => "confirmed broken",
+            State::Fixed => "fixed",
+        }
+    }
+}
+
+/// State with the failing runs in a row leading up to it
+#[derive(Clone, Copy)]
+struct Tracker {
+    state: State,
+    failures: usize,
+}
+
+/// Moves on to the next run's status, which is left out when the run got no result for it
+fn step(tracker: Option<Tracker>, status: Status, broken_after: usize) -> Option<Tracker> {
+    if matches!(
+        status,
+        Status::Unchecked | Status::TooLarge | Status::InfraError
+    ) {
+        return tracker;
+    }
+
+    let previous = tracker.map(|tracker| tracker.state);
+    if !status.is_failure() {
+        let state = match previous {
+            Some(State::ConfirmedBroken) => State::Fixed,
+            _ => State::Passing,
+        };
+        return Some(Tracker { state, failures: 0 });
+    }
+
+    let failures = tracker.map_or(0, |tracker| tracker.failures) + 1;
+    let state = if failures >= broken_after || previous == Some(State::ConfirmedBroken) {
+        State::ConfirmedBroken
+    } else {
+        State::Suspect
+    };
+    Some(Tracker { state, failures })
+}
+
+/// Sets each result's state from the statuses it had in the history's runs followed by this one,
+/// confirming failures once they lasted `broken_after` runs in a row
+pub fn apply(history: Option<&History>, results: &mut [FodResult], broken_after: usize) {
+    let mut trackers = HashMap::<(&str, &str), Option<Tracker>>::new();
+    for run in history.iter().flat_map(|history| &history.runs) {
+        for record in &run.records {
+            let tracker = trackers.entry((&record.attr, &record.output)).or_default();
+            *tracker = step(*tracker, record.status, broken_after);
+        }
+    }
+
+    for result in results {
+        let tracker = trackers
+            .get(&(result.attr.as_str(), result.output.as_str()))
+            .copied()
+            .flatten();
+        result.state = step(tracker, result.status, broken_after).map(|tracker| tracker.state);
+    }
+}
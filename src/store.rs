@@ -0,0 +1,210 @@
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use tempfile::{tempdir, TempDir};
+
+use crate::disk::store_dir;
+use crate::options::Options;
+
+pub const ACCESS_MODES: &[&str] = &["auto", "daemon", "local"];
+
+extern "C" {
+    fn access(path: *const std::ffi::c_char, mode: i32) -> i32;
+}
+
+const W_OK: i32 = 2;
+
+/// Directory the private store of `--private-store` keeps its paths under
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where a store path is on disk, which is under the private store's root when there is one
+pub fn physical(path: &Path) -> PathBuf {
+    match ROOT.get() {
+        Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+        None => path.to_owned(),
+    }
+}
+
+fn state_dir() -> PathBuf {
+    env::var_os("NIX_STATE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from("/nix/var/nix"))
+}
+
+fn daemon_socket() -> PathBuf {
+    env::var_os("NIX_DAEMON_SOCKET_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| state_dir().join("daemon-socket/socket"))
+}
+
+fn writable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // SAFETY: the path is a valid C string for the duration of the call
+    unsafe { access(path.as_ptr(), W_OK) == 0 }
+}
+
+/// Whether the store and its database can be written without going through a daemon
+fn local_usable() -> bool {
+    writable(Path::new(&store_dir())) && writable(&state_dir().join("db"))
+}
+
+fn daemon_usable() -> bool {
+    UnixStream::connect(daemon_socket()).is_ok()
+}
+
+/// Whether unprivileged users can create user namespaces, which Nix builds in for a private store
+fn user_namespaces() -> bool {
+    let enabled = |path: &str| fs::read_to_string(path).map_or(true, |value| value.trim() != "0");
+
+    enabled("/proc/sys/user/max_user_namespaces")
+        && enabled("/proc/sys/kernel/unprivileged_userns_clone")
+}
+
+/// Private store rooted at `dir`, where Nix keeps every path and realises in a user and mount
+/// namespace, so the host's store is neither written to nor collected
+fn private(dir: &Path) -> Result<String> {
+    if !user_namespaces() {
+        bail!("User namespaces are disabled, so Nix cannot build in a private store");
+    }
+
+    fs::create_dir_all(dir).context(format!("Creating private store in {}", dir.display()))?;
+    let root = fs::canonicalize(dir).context(format!("Resolving path {}", dir.display()))?;
+    if !writable(&root) {
+        bail!(
+            "Private store directory {} is not writable by the current user",
+            root.display()
+        );
+    }
+
+    let store = root.display().to_string();
+    ROOT.set(root).expect("Private store root set once");
+    Ok(store)
+}
+
+/// Store given with `--nix-store-uri`, used as is, noting the root of a local store kept elsewhere
+/// so its paths are read from there
+fn explicit(uri: &str) -> String {
+    let root = if uri.starts_with('/') {
+        uri.split('?').next()
+    } else {
+        uri.strip_prefix("local?").and_then(|params| {
+            params
+                .split('&')
+                .find_map(|param| param.strip_prefix("root="))
+        })
+    };
+    if let Some(root) = root {
+        ROOT.set(PathBuf::from(root)).expect("Store root set once");
+    }
+
+    uri.to_string()
+}
+
+/// Local store, naming its state directory when it is not Nix's default as Nix commands are run
+/// without our environment
+fn local() -> String {
+    match env::var_os("NIX_STATE_DIR") {
+        Some(_) => format!("local?state={}", state_dir().display()),
+        None => "local".to_string(),
+    }
+}
+
+/// Daemon at the socket checked, rather than whichever one Nix would default to
+fn daemon() -> String {
+    format!("unix://{}", daemon_socket().display())
+}
+
+/// Store Nix commands should use, checking it can be used: the one `--nix-store-uri` gives, the
+/// private store when there is one, otherwise the one `--store-access` asks for, with `auto`
+/// picking the local store when it is writable and the daemon otherwise
+///
+/// Nix itself would fall back to a store under the home directory when neither can be used, which
+/// would have every realisation fail or fetch into a store nobody looks at.
+pub fn resolve(opts: &Options) -> Result<String> {
+    if let Some(uri) = &opts.nix_store_uri {
+        return Ok(explicit(uri));
+    }
+    if let Some(dir) = &opts.private_store {
+        return private(dir);
+    }
+
+    let store = match opts.store_access.as_str() {
+        "local" if local_usable() => local(),
+        "local" => bail!(
+            "The Nix store {} or its database in {} is not writable by the current user, use --store-access daemon to go through nix-daemon",
+            store_dir(),
+            state_dir().display()
+        ),
+        "daemon" if daemon_usable() => daemon(),
+        "daemon" => bail!(
+            "Cannot connect to nix-daemon at {}, is it running?",
+            daemon_socket().display()
+        ),
+        _ if local_usable() => local(),
+        _ if daemon_usable() => daemon(),
+        _ => bail!(
+            "The Nix store {} is not writable by the current user and nix-daemon is not reachable at {}, so Nix cannot realise anything",
+            store_dir(),
+            daemon_socket().display()
+        ),
+    };
+
+    Ok(store)
+}
+
+/// Store of its own in a temporary directory, for fetching an output again without the store in use
+/// handing back the copy it already has
+pub struct Scratch {
+    dir: TempDir,
+}
+
+impl Scratch {
+    pub fn new() -> Result<Self> {
+        Ok(Scratch {
+            dir: tempdir().context("Creating temporary directory for scratch store")?,
+        })
+    }
+
+    pub fn uri(&self) -> String {
+        self.dir.path().display().to_string()
+    }
+}
+
+/// Gives back the write permission Nix takes from store directories, which removing them needs
+fn make_writable(dir: &Path) -> io::Result<()> {
+    let mut permissions = fs::symlink_metadata(dir)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o700);
+    fs::set_permissions(dir, permissions)?;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            make_writable(&entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        if let Err(err) = make_writable(self.dir.path()) {
+            warn!(
+                "Error making scratch store {} removable, ignoring: {}",
+                self.dir.path().display(),
+                err
+            );
+        }
+    }
+}
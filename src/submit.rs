@@ -0,0 +1,58 @@
+use std::env::consts;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::history::now;
+use crate::http;
+use crate::report::Report;
+
+/// Version of the upload format, bumped when fields change meaning or go away
+const PROTOCOL_VERSION: u64 = 1;
+
+/// Upload of one run to an aggregation server, sent as a JSON POST with an
+/// `Authorization: Bearer <TOKEN>` header
+///
+/// Besides the results, it has what a server needs to put runs from many machines together: the
+/// Nixpkgs revision, the store URI Nix was given, the shard of the attrs checked as
+/// `{ "index": I, "count": N }` or null for all of them, and the system it ran on. Servers should
+/// answer with a 2xx status once the run is stored, and may return `{ "url": ... }` pointing to
+/// where it shows up.
+pub fn payload(
+    report: &Report,
+    started: u64,
+    revision: Option<&str>,
+    shard: Option<(usize, usize)>,
+) -> Value {
+    json!({
+        "version": PROTOCOL_VERSION,
+        "client": format!("nixpkgs-fod-reports/{}", env!("CARGO_PKG_VERSION")),
+        "system": format!("{}-{}", consts::ARCH, consts::OS),
+        "started": started,
+        "finished": now(),
+        "revision": revision,
+        "store": report.store,
+        "shard": shard.map(|(index, count)| json!({ "index": index, "count": count })),
+        "skipped": report.skipped,
+        "downloaded": report.downloaded,
+        "results": report
+            .results
+            .iter()
+            .map(|result| result.to_json())
+            .collect::<Value>(),
+    })
+}
+
+/// Uploads the payload, returning where the server says the run can be seen
+pub fn submit(url: &str, token: &str, payload: &Value) -> Result<Option<String>> {
+    let response = http::post(
+        url,
+        &[format!("Authorization: Bearer {}", token)],
+        Some(payload),
+    )?;
+
+    // The response body is optional, so only a URL in it is of interest
+    Ok(serde_json::from_slice::<Value>(&response)
+        .ok()
+        .and_then(|response| response["url"].as_str().map(String::from)))
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+
+use crate::drv::Derivation;
+use crate::http;
+
+const SWH_API: &str = "https://archive.softwareheritage.org/api/1";
+
+#[derive(Debug, Clone)]
+pub struct SwhStatus {
+    pub archived: bool,
+    pub save_request: Option<String>,
+}
+
+/// Git origin a FOD's source comes from, if it can be told from its URLs
+fn git_origin(derivation: &Derivation, urls: &[String]) -> Option<String> {
+    urls.iter().find_map(|url| {
+        if derivation.env.contains_key("rev") && !url.starts_with("mirror://") {
+            return Some(url.trim_end_matches('/').to_string());
+        }
+
+        let rest = url
+            .strip_prefix("https://github.com/")
+            .or_else(|| url.strip_prefix("https://gitlab.com/"))?;
+        let (repo, _) = rest
+            .split_once("/archive/")
+            .or_else(|| rest.split_once("/-/archive/"))?;
+
+        Some(format!("{}{}", &url[..url.len() - rest.len()], repo))
+    })
+}
+
+fn origin_archived(origin: &str) -> Result<bool> {
+    Ok(http::get_json(&format!("{}/origin/{}/get/", SWH_API, origin))?.is_some())
+}
+
+pub fn lookup(derivation: &Derivation, urls: &[String], save: bool) -> Result<SwhStatus> {
+    let mut status = SwhStatus {
+        archived: false,
+        save_request: None,
+    };
+
+    // Flat hashes are plain content hashes that the archive indexes directly
+    if let Some(output) = derivation.outputs.first() {
+        if output.hash_algo == "sha256" {
+            status.archived =
+                http::get_json(&format!("{}/content/sha256:{}/", SWH_API, output.hash))?.is_some();
+        }
+    }
+
+    if let Some(origin) = git_origin(derivation, urls) {
+        if !status.archived {
+            status.archived = origin_archived(&origin)?;
+        }
+
+        if save {
+            let headers = std::env::var("SWH_TOKEN")
+                .map(|token| vec![format!("Authorization: Bearer {}", token)])
+                .unwrap_or_default();
+
+            let response = http::post_json(
+                &format!("{}/origin/save/git/url/{}/", SWH_API, origin),
+                &headers,
+                None,
+            )?;
+
+            status.save_request = response["save_request_status"].as_str().map(String::from);
+        }
+    }
+
+    Ok(status)
+}
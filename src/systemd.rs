@@ -0,0 +1,125 @@
+use std::env;
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SIGTERM: i32 = 15;
+
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// When the service started, which `PROGRESS` counts from
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Milliseconds after `STARTED` that work last moved forward
+static PROGRESS: AtomicU64 = AtomicU64::new(0);
+
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+}
+
+extern "C" fn request_stop(_signum: i32) {
+    STOPPING.store(true, Ordering::Relaxed);
+}
+
+pub fn under_systemd() -> bool {
+    env::var_os("INVOCATION_ID").is_some() || env::var_os("NOTIFY_SOCKET").is_some()
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_address(name: &str) -> io::Result<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+
+    SocketAddr::from_abstract_name(name)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn abstract_address(_name: &str) -> io::Result<SocketAddr> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Sends a state change to the service manager, doing nothing outside of a notify service
+pub fn notify(state: &str) {
+    let Some(socket) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let socket = socket.to_string_lossy();
+
+    let address = match socket.strip_prefix('@') {
+        Some(name) => abstract_address(name),
+        None => SocketAddr::from_pathname(&*socket),
+    };
+
+    if let Err(err) = address
+        .and_then(|address| UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address))
+    {
+        warn!("Error notifying systemd, ignoring: {}", err);
+    }
+}
+
+pub fn status(status: &str) {
+    progress();
+    notify(&format!("STATUS={}", status));
+}
+
+/// Notes that work moved forward, which keeps the watchdog fed for another interval
+pub fn progress() {
+    if let Some(started) = STARTED.get() {
+        PROGRESS.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Stops on SIGTERM once in-flight work is done and feeds the watchdog while work keeps moving
+/// forward, when run by systemd
+///
+/// A run stuck with no Nix command logging or finishing and no job done for a whole watchdog
+/// interval stops feeding it, so systemd restarts the service rather than waiting on it forever.
+pub fn start() {
+    if !under_systemd() {
+        return;
+    }
+    let started = *STARTED.get_or_init(Instant::now);
+
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        signal(SIGTERM, request_stop);
+    }
+
+    if let Some(usec) = env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+    {
+        let interval = Duration::from_micros(usec);
+        thread::spawn(move || {
+            let mut stalled = false;
+            loop {
+                let progress = Duration::from_millis(PROGRESS.load(Ordering::Relaxed));
+                let idle = started.elapsed().saturating_sub(progress);
+
+                if idle < interval {
+                    stalled = false;
+                    notify("WATCHDOG=1");
+                } else if !stalled {
+                    stalled = true;
+                    warn!(
+                        "No progress for {}s, no longer feeding the systemd watchdog",
+                        idle.as_secs()
+                    );
+                }
+                thread::sleep(interval / 2);
+            }
+        });
+    }
+}
+
+pub fn stopping() -> bool {
+    STOPPING.load(Ordering::Relaxed)
+}
+
+/// First of the directories systemd set up for StateDirectory=
+pub fn state_directory() -> Option<PathBuf> {
+    env::var_os("STATE_DIRECTORY").and_then(|dirs| env::split_paths(&dirs).next())
+}
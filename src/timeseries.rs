@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::history::{History, Record, Run};
+use crate::report::Status;
+
+/// FOD outputs checked and broken in a run, over all of them or those of one fetcher
+#[derive(Default)]
+struct Counts {
+    checked: usize,
+    broken: usize,
+}
+
+/// Whether a record got a result from upstream, as far as the history can tell
+fn checked(record: &Record) -> bool {
+    !matches!(
+        record.status,
+        Status::Unchecked | Status::TooLarge | Status::InfraError
+    )
+}
+
+/// Totals of a run, then the same by fetcher, with records from before fetchers were recorded
+/// under `unknown`
+fn aggregate(run: &Run) -> (Counts, BTreeMap<&str, Counts>) {
+    let mut total = Counts::default();
+    let mut by_fetcher = BTreeMap::<&str, Counts>::new();
+
+    for record in run.records.iter().filter(|record| checked(record)) {
+        let counts = by_fetcher
+            .entry(record.fetcher.as_deref().unwrap_or("unknown"))
+            .or_default();
+        for counts in [&mut total, counts] {
+            counts.checked += 1;
+            if record.status.is_failure() {
+                counts.broken += 1;
+            }
+        }
+    }
+
+    (total, by_fetcher)
+}
+
+/// Aggregates of each run, oldest first, with times in milliseconds as Grafana expects
+pub fn points(history: &History) -> Value {
+    history
+        .runs
+        .iter()
+        .map(|run| {
+            let (total, by_fetcher) = aggregate(run);
+
+            json!({
+                "time": run.started * 1000,
+                "revision": run.revision,
+                "checked": total.checked,
+                "broken": total.broken,
+                "fetchers": by_fetcher
+                    .iter()
+                    .map(|(fetcher, counts)| {
+                        (
+                            fetcher.to_string(),
+                            json!({ "checked": counts.checked, "broken": counts.broken }),
+                        )
+                    })
+                    .collect::<serde_json::Map<String, Value>>(),
+            })
+        })
+        .collect()
+}
+
+/// Renders the aggregates as JSON, or as CSV with a row for the totals of each run under the
+/// fetcher `all` followed by a row for each fetcher
+pub fn render(history: &History, format: &str) -> String {
+    if format != "csv" {
+        return format!("{}\n", points(history));
+    }
+
+    let mut csv = "time,revision,fetcher,checked,broken\n".to_string();
+    for run in &history.runs {
+        let (total, by_fetcher) = aggregate(run);
+
+        for (fetcher, counts) in [("all", &total)].into_iter().chain(
+            by_fetcher
+                .iter()
+                .map(|(fetcher, counts)| (*fetcher, counts)),
+        ) {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                run.started * 1000,
+                run.revision.as_deref().unwrap_or_default(),
+                fetcher,
+                counts.checked,
+                counts.broken
+            ));
+        }
+    }
+
+    csv
+}
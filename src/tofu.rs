@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::history::now;
+use crate::report::{FodResult, Status};
+
+/// Content first seen for a FOD output, whether or not it matched the hash in Nixpkgs
+pub struct Entry {
+    pub hash: String,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub urls: Vec<String>,
+}
+
+/// Trust-on-first-use database of the content upstreams served, keyed by output path so it stays
+/// put across Nixpkgs revisions that leave the FOD alone
+pub struct Tofu {
+    path: PathBuf,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// Hash of what upstream served, when the check got far enough to know
+fn served(result: &FodResult) -> Option<&str> {
+    match result.status {
+        Status::Reproducible => Some(&result.expected_hash),
+        Status::NotReproducible => result.got_hash.as_deref(),
+        _ => None,
+    }
+}
+
+impl Entry {
+    fn to_json(&self) -> Value {
+        json!({
+            "hash": self.hash,
+            "first_seen": self.first_seen,
+            "last_seen": self.last_seen,
+            "urls": self.urls,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Entry> {
+        Some(Entry {
+            hash: value["hash"].as_str()?.to_string(),
+            first_seen: value["first_seen"].as_u64()?,
+            last_seen: value["last_seen"].as_u64()?,
+            urls: value["urls"]
+                .as_array()?
+                .iter()
+                .filter_map(|url| url.as_str().map(String::from))
+                .collect(),
+        })
+    }
+}
+
+impl Tofu {
+    pub fn load(path: &Path) -> Result<Tofu> {
+        let mut tofu = Tofu {
+            path: path.to_owned(),
+            entries: BTreeMap::new(),
+        };
+
+        if !path.try_exists().unwrap_or(false) {
+            return Ok(tofu);
+        }
+
+        let value = serde_json::from_str::<Value>(
+            &fs::read_to_string(path).context("Reading TOFU database")?,
+        )
+        .context("Deserializing TOFU database")?;
+
+        for (out, entry) in value.as_object().into_iter().flatten() {
+            tofu.entries.insert(
+                out.clone(),
+                Entry::from_json(entry).ok_or(anyhow!("Invalid TOFU entry for {}", out))?,
+            );
+        }
+
+        Ok(tofu)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let value = self
+            .entries
+            .iter()
+            .map(|(out, entry)| (out.clone(), entry.to_json()))
+            .collect::<serde_json::Map<String, Value>>();
+
+        fs::write(&self.path, Value::Object(value).to_string()).context("Writing TOFU database")
+    }
+
+    /// Records what upstream served for `result`, returning the first seen entry if it differs
+    pub fn observe(&mut self, result: &FodResult) -> Option<&Entry> {
+        let hash = served(result)?;
+        let now = now();
+
+        let out = result.out.display().to_string();
+        let changed = match self.entries.get_mut(&out) {
+            Some(entry) => {
+                entry.last_seen = now;
+                entry.hash != hash
+            }
+            None => {
+                self.entries.insert(
+                    out.clone(),
+                    Entry {
+                        hash: hash.to_string(),
+                        first_seen: now,
+                        last_seen: now,
+                        urls: result.urls.clone(),
+                    },
+                );
+                false
+            }
+        };
+
+        if changed {
+            self.entries.get(&out)
+        } else {
+            None
+        }
+    }
+}
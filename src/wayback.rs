@@ -0,0 +1,32 @@
+use anyhow::Result;
+
+use crate::http;
+
+const WAYBACK_API: &str = "https://archive.org/wayback/available";
+
+pub fn snapshot(url: &str) -> Result<Option<String>> {
+    let Some(response) = http::get_json(&format!("{}?url={}", WAYBACK_API, http::encode(url)))?
+    else {
+        return Ok(None);
+    };
+
+    let closest = &response["archived_snapshots"]["closest"];
+
+    if closest["available"].as_bool() == Some(true) && closest["status"].as_str() == Some("200") {
+        Ok(closest["url"].as_str().map(String::from))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn first_snapshot(urls: &[String]) -> Result<Option<String>> {
+    for url in urls {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            if let Some(snapshot) = snapshot(url)? {
+                return Ok(Some(snapshot));
+            }
+        }
+    }
+
+    Ok(None)
+}